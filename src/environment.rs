@@ -0,0 +1,251 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::error::RuntimeErrorKind;
+use crate::typeck::{Declared, Symbol};
+use crate::types::{Routine, Selector, Type, Value};
+
+#[derive(Debug)]
+struct Variable {
+    /// None for constants, which cannot be assigned
+    ty: Option<Type>,
+    /// None until the first assignment
+    value: Option<Value>,
+    /// for `var` parameters, the scope holding the caller's variable, its
+    /// name and the path to the part passed, every read and write goes there
+    alias: Option<(Rc<RefCell<Environment>>, String, Vec<Selector>)>,
+}
+
+/// One scope of variables, routines and types, chained to the scope it is nested in.
+/// Pascal names are case insensitive, so every name is stored lowercased
+#[derive(Debug, Default)]
+pub struct Environment {
+    values: HashMap<String, Variable>,
+    routines: HashMap<String, Rc<Routine>>,
+    types: HashMap<String, Type>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Environment::default()
+    }
+
+    /// a new scope nested inside `enclosing`
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Environment {
+            values: HashMap::new(),
+            routines: HashMap::new(),
+            types: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    /// declare an uninitialized variable in this scope, shadowing outer ones
+    pub fn declare(&mut self, name: &str, ty: Type) {
+        let variable = Variable {
+            value: ty.initial(),
+            ty: Some(ty),
+            alias: None,
+        };
+        self.values.insert(name.to_lowercase(), variable);
+    }
+
+    /// define a constant in this scope
+    pub fn define(&mut self, name: &str, value: Value) {
+        let variable = Variable {
+            ty: None,
+            value: Some(value),
+            alias: None,
+        };
+        self.values.insert(name.to_lowercase(), variable);
+    }
+
+    /// declare `name` as another name for the part of `target` at `path`,
+    /// `target` being declared in the `owner` scope
+    pub fn declare_alias(
+        &mut self,
+        name: &str,
+        owner: Rc<RefCell<Environment>>,
+        target: &str,
+        path: Vec<Selector>,
+    ) {
+        let variable = Variable {
+            ty: None,
+            value: None,
+            alias: Some((owner, target.to_string(), path)),
+        };
+        self.values.insert(name.to_lowercase(), variable);
+    }
+
+    /// the scope `name` is declared in, seen from `env`
+    pub fn owner(env: &Rc<RefCell<Environment>>, name: &str) -> Option<Rc<RefCell<Environment>>> {
+        let scope = env.borrow();
+        if scope.values.contains_key(&name.to_lowercase()) {
+            return Some(Rc::clone(env));
+        }
+        scope
+            .enclosing
+            .as_ref()
+            .and_then(|enclosing| Environment::owner(enclosing, name))
+    }
+
+    /// declared type of the part at `path` of the nearest variable called
+    /// `name`, None when it is a constant
+    pub fn type_at(&self, name: &str, path: &[Selector]) -> Result<Option<Type>, RuntimeErrorKind> {
+        match self.values.get(&name.to_lowercase()) {
+            Some(Variable {
+                alias: Some((owner, target, prefix)),
+                ..
+            }) => owner.borrow().type_at(target, &[prefix, path].concat()),
+            Some(Variable { ty: Some(ty), .. }) => {
+                let mut ty = ty;
+                for selector in path {
+                    ty = ty.select(selector)?;
+                }
+                Ok(Some(ty.clone()))
+            }
+            Some(Variable { ty: None, .. }) => Ok(None),
+            None => match &self.enclosing {
+                Some(enclosing) => enclosing.borrow().type_at(name, path),
+                None => Err(RuntimeErrorKind::UndeclaredVariable(name.to_string())),
+            },
+        }
+    }
+
+    /// declare a function or procedure in this scope
+    pub fn define_routine(&mut self, routine: Rc<Routine>) {
+        let name = routine.name.token.to_string().to_lowercase();
+        self.routines.insert(name, routine);
+    }
+
+    /// declare a type alias in this scope, shadowing outer ones
+    pub fn define_type(&mut self, name: &str, ty: Type) {
+        self.types.insert(name.to_lowercase(), ty);
+    }
+
+    /// the nearest type alias called `name`
+    pub fn find_type(&self, name: &str) -> Option<Type> {
+        match self.types.get(&name.to_lowercase()) {
+            Some(ty) => Some(ty.clone()),
+            None => self.enclosing.as_ref()?.borrow().find_type(name),
+        }
+    }
+
+    /// the nearest routine called `name`, with the scope it was declared in,
+    /// which is the scope its body runs nested in
+    pub fn find_routine(
+        env: &Rc<RefCell<Environment>>,
+        name: &str,
+    ) -> Option<(Rc<Routine>, Rc<RefCell<Environment>>)> {
+        let scope = env.borrow();
+        match scope.routines.get(&name.to_lowercase()) {
+            Some(routine) => Some((Rc::clone(routine), Rc::clone(env))),
+            None => scope
+                .enclosing
+                .as_ref()
+                .and_then(|enclosing| Environment::find_routine(enclosing, name)),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Result<Value, RuntimeErrorKind> {
+        self.get_at(name, &[])
+    }
+
+    /// the part at `path` of the nearest variable called `name`
+    pub fn get_at(&self, name: &str, path: &[Selector]) -> Result<Value, RuntimeErrorKind> {
+        match self.values.get(&name.to_lowercase()) {
+            Some(Variable {
+                alias: Some((owner, target, prefix)),
+                ..
+            }) => owner.borrow().get_at(target, &[prefix, path].concat()),
+            Some(Variable { value: Some(v), .. }) => {
+                let mut v = v;
+                for selector in path {
+                    v = v.select(selector)?;
+                }
+                Ok(v.clone())
+            }
+            Some(Variable { value: None, .. }) => {
+                Err(RuntimeErrorKind::UninitializedVariable(name.to_string()))
+            }
+            None => match &self.enclosing {
+                Some(enclosing) => enclosing.borrow().get_at(name, path),
+                None => Err(RuntimeErrorKind::UndeclaredVariable(name.to_string())),
+            },
+        }
+    }
+
+    pub fn assign(&mut self, name: &str, value: Value) -> Result<(), RuntimeErrorKind> {
+        self.assign_at(name, &[], value)
+    }
+
+    /// overwrite the part at `path` of the nearest variable called `name`,
+    /// converting the value to the declared type of that part
+    pub fn assign_at(
+        &mut self,
+        name: &str,
+        path: &[Selector],
+        value: Value,
+    ) -> Result<(), RuntimeErrorKind> {
+        match self.values.get_mut(&name.to_lowercase()) {
+            Some(Variable {
+                alias: Some((owner, target, prefix)),
+                ..
+            }) => owner
+                .borrow_mut()
+                .assign_at(target, &[prefix, path].concat(), value),
+            Some(Variable {
+                ty: Some(ty),
+                value: slot,
+                ..
+            }) => {
+                if path.is_empty() {
+                    *slot = Some(ty.coerce(value)?);
+                    return Ok(());
+                }
+                let mut ty = &*ty;
+                let mut part = slot
+                    .as_mut()
+                    .ok_or_else(|| RuntimeErrorKind::UninitializedVariable(name.to_string()))?;
+                for selector in path {
+                    ty = ty.select(selector)?;
+                    part = part.select_mut(selector)?;
+                }
+                *part = ty.coerce(value)?;
+                Ok(())
+            }
+            Some(Variable { ty: None, .. }) => {
+                Err(RuntimeErrorKind::AssignToConstant(name.to_string()))
+            }
+            None => match &self.enclosing {
+                Some(enclosing) => enclosing.borrow_mut().assign_at(name, path, value),
+                None => Err(RuntimeErrorKind::UndeclaredVariable(name.to_string())),
+            },
+        }
+    }
+}
+
+impl Declared for Environment {
+    fn var(&self, name: &str) -> Option<Symbol> {
+        match self.type_at(name, &[]).ok()? {
+            Some(ty) => Some(Symbol::Var(Some(ty))),
+            None => {
+                let value = self.get(name).ok()?;
+                Some(Symbol::Const((Type::of(&value), Some(value))))
+            }
+        }
+    }
+
+    fn routine(&self, name: &str) -> Option<Rc<Routine>> {
+        match self.routines.get(&name.to_lowercase()) {
+            Some(routine) => Some(Rc::clone(routine)),
+            None => self.enclosing.as_ref()?.borrow().routine(name),
+        }
+    }
+
+    fn type_alias(&self, name: &str) -> Option<Type> {
+        self.find_type(name)
+    }
+}
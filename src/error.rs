@@ -1,5 +1,380 @@
+use core::fmt;
+
+use crate::types::{Span, Token};
+
 #[derive(Debug)]
 pub enum DuYError {
-    InvalidToken,
-    InvalidIdentifier(String),
+    Lex(LexError),
+    Parse(ParseError),
+    Runtime(RuntimeError),
+}
+
+impl fmt::Display for DuYError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DuYError::Lex(e) => write!(f, "{}", e),
+            DuYError::Parse(e) => write!(f, "{}", e),
+            DuYError::Runtime(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<LexError> for DuYError {
+    fn from(e: LexError) -> Self {
+        DuYError::Lex(e)
+    }
+}
+
+impl From<ParseError> for DuYError {
+    fn from(e: ParseError) -> Self {
+        DuYError::Parse(e)
+    }
+}
+
+impl From<RuntimeError> for DuYError {
+    fn from(e: RuntimeError) -> Self {
+        DuYError::Runtime(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexErrorKind {
+    /// a char that starts no token
+    InvalidChar(char),
+    /// a string literal still open at the end of its line
+    UnterminatedString,
+    /// a block comment still open at the end of the source
+    UnterminatedComment,
+    /// a `\` with nothing after it on the line
+    UnterminatedEscape,
+    /// the char after a `\` in a string literal
+    InvalidEscape(char),
+    /// `#` and the digits after it, which name no character
+    InvalidCharCode(String),
+    /// a number literal with stray characters, missing digits or misplaced `_`
+    InvalidNumber(String),
+    /// an integer literal too big for an integer
+    IntegerOutOfRange(String),
+    /// a real literal too big for a real
+    RealOutOfRange(String),
+    /// the text of a directive with arguments it does not take
+    InvalidDirective(String),
+    /// `ELSE` or `ENDIF` with no `IFDEF` open
+    UnmatchedDirective(String),
+    /// an `IFDEF` or `IFNDEF` still open at the end of its file
+    UnterminatedCondition,
+    /// the file named by `{$I}` and why it could not be read
+    Include(String, String),
+}
+
+impl fmt::Display for LexErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexErrorKind::InvalidChar(c) => write!(f, "unexpected character `{}`", c),
+            LexErrorKind::UnterminatedString => write!(f, "unterminated string literal"),
+            LexErrorKind::UnterminatedComment => write!(f, "unterminated comment"),
+            LexErrorKind::UnterminatedEscape => write!(f, "unterminated escape sequence"),
+            LexErrorKind::InvalidEscape(c) => write!(f, "unknown escape sequence `\\{}`", c),
+            LexErrorKind::InvalidCharCode(code) => {
+                write!(f, "`{}` is not a valid character code", code)
+            }
+            LexErrorKind::InvalidNumber(text) => write!(f, "invalid number `{}`", text),
+            LexErrorKind::IntegerOutOfRange(text) => {
+                write!(f, "integer literal `{}` out of range", text)
+            }
+            LexErrorKind::RealOutOfRange(text) => write!(f, "real literal `{}` out of range", text),
+            LexErrorKind::InvalidDirective(text) => write!(f, "invalid directive `{{${}}}`", text),
+            LexErrorKind::UnmatchedDirective(name) => {
+                write!(f, "`{{${}}}` without a matching `{{$IFDEF}}`", name)
+            }
+            LexErrorKind::UnterminatedCondition => {
+                write!(f, "`{{$IFDEF}}` without a matching `{{$ENDIF}}`")
+            }
+            LexErrorKind::Include(path, reason) => {
+                write!(f, "cannot include `{}`: {}", path, reason)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    /// the offending characters, or where the unterminated construct opened
+    pub span: Span,
+}
+
+impl LexError {
+    pub fn new(kind: LexErrorKind, span: Span) -> Self {
+        LexError { kind, span }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.kind)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    UnexpectedToken,
+    ExpectedExpression,
+    ExpectedStatement,
+    MissingCloseParen,
+    MissingSemiColon,
+    OutsideLoop,
+    /// the statement and expression nesting limit that was hit
+    NestingTooDeep(usize),
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::UnexpectedToken => write!(f, "unexpected token"),
+            ParseErrorKind::ExpectedExpression => write!(f, "expected expression"),
+            ParseErrorKind::ExpectedStatement => write!(f, "expected statement"),
+            ParseErrorKind::MissingCloseParen => write!(f, "missing `)`"),
+            ParseErrorKind::MissingSemiColon => write!(f, "missing `;`"),
+            ParseErrorKind::OutsideLoop => write!(f, "break or continue outside of a loop"),
+            ParseErrorKind::NestingTooDeep(depth) => {
+                write!(
+                    f,
+                    "statements and expressions nest more than {} deep",
+                    depth
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    /// tokens that would have been accepted, literals only matter by their kind
+    pub expected: Vec<Token>,
+    pub found: Token,
+    pub span: Span,
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, expected: Vec<Token>, found: Token, span: Span) -> Self {
+        ParseError {
+            kind,
+            expected,
+            found,
+            span,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.kind)?;
+        if !self.expected.is_empty() {
+            let expected: Vec<String> = self.expected.iter().map(describe).collect();
+            write!(f, ", expected {}", expected.join(" or "))?;
+        }
+        write!(f, ", found {}", describe(&self.found))
+    }
+}
+
+/// name of a token as shown in diagnostics
+fn describe(tok: &Token) -> String {
+    match tok {
+        Token::Identifier(_) => String::from("identifier"),
+        Token::StringLiteral(_) => String::from("string literal"),
+        Token::IntegerLiteral(_) => String::from("integer literal"),
+        Token::FloatLiteral(_) => String::from("real literal"),
+        Token::BooleanLiteral(_) => String::from("boolean literal"),
+        Token::EOF => String::from("end of file"),
+        _ => format!("`{}`", tok),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeErrorKind {
+    /// operation and the types it was given, e.g. "cannot add boolean and integer"
+    TypeMismatch(String),
+    DivisionByZero,
+    Overflow,
+    NegativeExponent,
+    NegativeRoot,
+    UndeclaredVariable(String),
+    UninitializedVariable(String),
+    AssignToConstant(String),
+    LoopVariableAssigned(String),
+    /// index, low and high bound of the array
+    IndexOutOfBounds(i64, i64, i64),
+    UnknownField(String),
+    /// an expression given where a variable is needed
+    NotAssignable(String),
+    /// routine name, expected and found argument counts
+    ArgumentCount(String, usize, usize),
+    /// a function finished without assigning its result
+    NoResult(String),
+    /// the call depth limit that was hit
+    StackOverflow(usize),
+    /// the statement and expression nesting limit that was hit
+    NestingTooDeep(usize),
+    UnknownType(String),
+    UnknownProcedure(String),
+    /// an operator or literal no value supports, e.g. "operator `..`"
+    Unsupported(String),
+    /// writing the program output failed
+    Io(String),
+    /// reading the program input failed or it held the wrong kind of value
+    Input(String),
+}
+
+impl fmt::Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeErrorKind::TypeMismatch(what) => write!(f, "type mismatch: {}", what),
+            RuntimeErrorKind::DivisionByZero => write!(f, "division by zero"),
+            RuntimeErrorKind::Overflow => write!(f, "arithmetic overflow"),
+            RuntimeErrorKind::NegativeExponent => {
+                write!(f, "negative exponent for an integer power")
+            }
+            RuntimeErrorKind::NegativeRoot => write!(f, "square root of a negative number"),
+            RuntimeErrorKind::UndeclaredVariable(name) => {
+                write!(f, "undeclared variable `{}`", name)
+            }
+            RuntimeErrorKind::UninitializedVariable(name) => {
+                write!(f, "variable `{}` is used before being assigned", name)
+            }
+            RuntimeErrorKind::AssignToConstant(name) => {
+                write!(f, "cannot assign to constant `{}`", name)
+            }
+            RuntimeErrorKind::LoopVariableAssigned(name) => {
+                write!(
+                    f,
+                    "cannot assign to loop variable `{}` inside its for loop",
+                    name
+                )
+            }
+            RuntimeErrorKind::ArgumentCount(name, expected, found) => write!(
+                f,
+                "`{}` expects {} arguments, found {}",
+                name, expected, found
+            ),
+            RuntimeErrorKind::NoResult(name) => {
+                write!(f, "function `{}` returned without assigning a result", name)
+            }
+            RuntimeErrorKind::StackOverflow(depth) => {
+                write!(f, "stack overflow: more than {} nested calls", depth)
+            }
+            RuntimeErrorKind::NestingTooDeep(depth) => write!(
+                f,
+                "stack overflow: statements and expressions nest more than {} deep",
+                depth
+            ),
+            RuntimeErrorKind::IndexOutOfBounds(i, low, high) if high < low => {
+                write!(f, "index {} out of bounds, the array is empty", i)
+            }
+            RuntimeErrorKind::IndexOutOfBounds(i, low, high) => {
+                write!(f, "index {} out of bounds {}..{}", i, low, high)
+            }
+            RuntimeErrorKind::UnknownField(name) => write!(f, "unknown field `{}`", name),
+            RuntimeErrorKind::NotAssignable(expr) => {
+                write!(
+                    f,
+                    "`{}` is not a variable that can be passed by reference",
+                    expr
+                )
+            }
+            RuntimeErrorKind::UnknownType(name) => write!(f, "unknown type `{}`", name),
+            RuntimeErrorKind::UnknownProcedure(name) => {
+                write!(f, "unknown procedure `{}`", name)
+            }
+            RuntimeErrorKind::Unsupported(what) => write!(f, "unsupported {}", what),
+            RuntimeErrorKind::Io(e) => write!(f, "cannot write output: {}", e),
+            RuntimeErrorKind::Input(e) => write!(f, "cannot read input: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    /// the expression or statement that failed
+    pub span: Span,
+}
+
+impl RuntimeError {
+    pub fn new(kind: RuntimeErrorKind, span: Span) -> Self {
+        RuntimeError { kind, span }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: runtime error: {}", self.span, self.kind)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeErrorKind {
+    Mismatch(String),
+    UndeclaredVariable(String),
+    UnknownType(String),
+    UnknownRoutine(String),
+    /// routine name, expected and found argument counts
+    ArgumentCount(String, usize, usize),
+    /// assigned to, or passed by reference, without being a variable
+    NotAssignable(String),
+    UnknownField(String),
+    /// array bounds that cannot be worked out before running
+    NotConstant,
+    /// the statement and expression nesting limit that was hit
+    NestingTooDeep(usize),
+}
+
+impl fmt::Display for TypeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeErrorKind::Mismatch(reason) => write!(f, "{}", reason),
+            TypeErrorKind::UndeclaredVariable(name) => {
+                write!(f, "undeclared variable `{}`", name)
+            }
+            TypeErrorKind::UnknownType(name) => write!(f, "unknown type `{}`", name),
+            TypeErrorKind::UnknownRoutine(name) => {
+                write!(f, "unknown function or procedure `{}`", name)
+            }
+            TypeErrorKind::ArgumentCount(name, expected, found) => write!(
+                f,
+                "`{}` expects {} arguments, found {}",
+                name, expected, found
+            ),
+            TypeErrorKind::NotAssignable(expr) => write!(f, "`{}` is not a variable", expr),
+            TypeErrorKind::UnknownField(name) => write!(f, "unknown field `{}`", name),
+            TypeErrorKind::NotConstant => write!(f, "array bounds must be constant integers"),
+            TypeErrorKind::NestingTooDeep(depth) => {
+                write!(
+                    f,
+                    "statements and expressions nest more than {} deep",
+                    depth
+                )
+            }
+        }
+    }
+}
+
+/// a type error found before the program runs
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+    pub kind: TypeErrorKind,
+    pub span: Span,
+}
+
+impl TypeError {
+    pub fn new(kind: TypeErrorKind, span: Span) -> Self {
+        TypeError { kind, span }
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: type error: {}", self.span, self.kind)
+    }
 }
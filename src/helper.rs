@@ -1,18 +1,34 @@
-use crate::{error::DuYError, types::Token};
+use crate::types::Token;
 
 pub fn tokenize_keyword(input: &str) -> Option<Token> {
     let input = input.to_lowercase();
     match input.as_str() {
+        "program" => Some(Token::Program),
+        "const" => Some(Token::Const),
+        "type" => Some(Token::Type),
         "var" => Some(Token::Var),
         "if" => Some(Token::If),
         "then" => Some(Token::Then),
         "else" => Some(Token::Else),
         "while" => Some(Token::While),
         "for" => Some(Token::For),
+        "to" => Some(Token::To),
+        "downto" => Some(Token::Downto),
         "do" => Some(Token::Do),
+        "repeat" => Some(Token::Repeat),
+        "until" => Some(Token::Until),
+        "break" => Some(Token::Break),
+        "continue" => Some(Token::Continue),
+        "exit" => Some(Token::Exit),
         "function" => Some(Token::Function),
+        "procedure" => Some(Token::Procedure),
         "begin" => Some(Token::Begin),
         "end" => Some(Token::End),
+        "array" => Some(Token::Array),
+        "of" => Some(Token::Of),
+        "record" => Some(Token::Record),
+        "with" => Some(Token::With),
+        "div" => Some(Token::Div),
         "mod" => Some(Token::Mod),
         "and" => Some(Token::And),
         "or" => Some(Token::Or),
@@ -26,96 +42,6 @@ pub fn tokenize_keyword(input: &str) -> Option<Token> {
         "endl" => Some(Token::Endl),
         "true" => Some(Token::BooleanLiteral(true)),
         "false" => Some(Token::BooleanLiteral(false)),
-
-        "write" => Some(Token::Write),
-        "read" => Some(Token::Read),
-        "sqrt" => Some(Token::Sqrt),
-        "abs" => Some(Token::Abs),
-        "sort" => Some(Token::Sort),
-        "len" => Some(Token::Len),
-        "endl" => Some(Token::Endl),
         _ => None,
     }
 }
-
-pub fn tokenize_ident(input: &str) -> Result<Token, DuYError> {
-    //name: cant start with number, cant contain space
-    if input.chars().nth(0).unwrap().is_digit(10) {
-        return Err(DuYError::InvalidIdentifier(
-            "Cant start with number".to_string(),
-        ));
-    }
-    if input.contains(" ") {
-        return Err(DuYError::InvalidIdentifier(
-            "Cant contain space".to_string(),
-        ));
-    }
-    let mut result = String::from("");
-    for char in input.chars() {
-        if char.is_alphanumeric() || char == "_".chars().nth(0).unwrap() {
-            result.push(char)
-        } else {
-            return Err(DuYError::InvalidIdentifier(
-                "Cant contain invalid character".to_string(),
-            ));
-        }
-    }
-
-    Ok(Token::Identifier(result))
-}
-
-pub fn tokenize_number_literals(input: &str) -> Result<Token, DuYError> {
-    let mut result = String::from("");
-
-    if input.matches(".").count() == 1 {
-        for char in input.chars() {
-            if char.is_digit(10) || char == ".".chars().nth(0).unwrap() {
-                result.push(char)
-            } else {
-                return Err(DuYError::InvalidToken);
-            }
-        }
-        return Ok(Token::FloatLiteral(result.parse::<f64>().unwrap()));
-    } else if !input.contains(".") {
-        for char in input.chars() {
-            if char.is_digit(10) {
-                result.push(char)
-            } else {
-                return Err(DuYError::InvalidToken);
-            }
-        }
-        return Ok(Token::IntegerLiteral(result.parse::<i64>().unwrap()));
-    } else {
-        return Err(DuYError::InvalidToken);
-    }
-}
-
-pub fn tokenize_string_literals(input: &str) -> Result<Token, DuYError> {
-    //does not support escape characters
-    if input.starts_with("'") && input.ends_with("'") {
-        let mut result = String::from("");
-        for _char in input.chars() {
-            if _char != '\'' {
-                result.push(_char);
-            }
-        }
-        return Ok(Token::StringLiteral(result));
-    }
-    Err(DuYError::InvalidToken)
-}
-
-pub fn skip_comments(src: &str) -> usize {
-    let pairs = [("{", "}")];
-
-    for &(pattern, matcher) in &pairs {
-        if src.starts_with(pattern) {
-            let matcher = matcher.chars().nth(0).unwrap();
-            for (pos, char) in src.chars().enumerate() {
-                if char == matcher {
-                    return pos - 1;
-                }
-            }
-        }
-    }
-    0
-}
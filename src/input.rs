@@ -0,0 +1,64 @@
+use std::io::{self, BufRead};
+
+/// Program input, consumed the way Pascal's `read` does: numbers are
+/// separated by any whitespace, strings run to the end of the current line
+pub struct Input {
+    reader: Box<dyn BufRead>,
+    /// what is left of the current line, newline included
+    line: String,
+}
+
+impl Input {
+    pub fn new(reader: impl BufRead + 'static) -> Self {
+        Input {
+            reader: Box::new(reader),
+            line: String::new(),
+        }
+    }
+
+    /// read the next line once the current one is used up, false at the end of input
+    fn fill(&mut self) -> io::Result<bool> {
+        if self.line.is_empty() {
+            self.reader.read_line(&mut self.line)?;
+        }
+        Ok(!self.line.is_empty())
+    }
+
+    /// the next whitespace separated word, None at the end of input
+    pub fn word(&mut self) -> io::Result<Option<String>> {
+        while self.fill()? {
+            let rest = self.line.trim_start();
+            if rest.is_empty() {
+                self.line.clear();
+                continue;
+            }
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let word = rest[..end].to_string();
+            self.line = rest[end..].to_string();
+            return Ok(Some(word));
+        }
+        Ok(None)
+    }
+
+    /// the rest of the current line, leaving its newline for `readln`
+    pub fn rest_of_line(&mut self) -> io::Result<String> {
+        self.fill()?;
+        let end = self.line.find(['\r', '\n']).unwrap_or(self.line.len());
+        Ok(self.line.drain(..end).collect())
+    }
+
+    /// the next character, newlines included, None at the end of input
+    pub fn char(&mut self) -> io::Result<Option<char>> {
+        if !self.fill()? {
+            return Ok(None);
+        }
+        Ok(Some(self.line.remove(0)))
+    }
+
+    /// skip past the end of the current line
+    pub fn skip_line(&mut self) -> io::Result<()> {
+        self.fill()?;
+        self.line.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,973 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::environment::Environment;
+use crate::error::{RuntimeError, RuntimeErrorKind};
+use crate::input::Input;
+use crate::typeck::TypeChecker;
+use crate::types::{
+    Block, Checks, Declaration, Expr, Param, ParamMode, Pow, Program, Routine, Selector, Span,
+    SpannedToken, Statement, StatementType, Token, Type, TypeSpec, Value, ValueResult, VarDecl,
+    WriteArg,
+};
+
+/// how deep routine calls may nest before the program is stopped
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+/// how many statements and expressions may nest per allowed call, so the
+/// native stack the interpreter needs is bounded by the call depth limit
+pub const NESTING_PER_CALL: usize = 16;
+
+/// how deep statements and expressions may nest when calls may nest
+/// `max_depth` deep. The parser and the type checker stop at the same depth
+pub fn max_nesting(max_depth: usize) -> usize {
+    max_depth.saturating_mul(NESTING_PER_CALL)
+}
+
+/// Tree walking interpreter, runs a parsed program reading `input` and writing its output to `out`
+pub struct Interpreter<'a> {
+    /// innermost scope, chained to the enclosing ones
+    env: Rc<RefCell<Environment>>,
+    /// lowercased control variables of the running for loops, which the
+    /// loop bodies must not assign
+    loop_vars: Vec<String>,
+    /// routine calls currently running
+    depth: usize,
+    max_depth: usize,
+    /// statements and expressions currently being run, calls included
+    nesting: usize,
+    /// the checks of the running statement
+    checks: Checks,
+    input: Input,
+    out: &'a mut dyn Write,
+}
+
+/// how control leaves a statement
+#[derive(Debug, PartialEq)]
+pub enum Flow {
+    Next,
+    Break,
+    Continue,
+    Exit,
+}
+
+/// an evaluated argument, or the variable a `var` parameter refers to
+enum Argument {
+    Value(Value, Span),
+    Ref(Reference),
+}
+
+/// the scope declaring a variable, its name and the path to a part of it
+type Reference = (Rc<RefCell<Environment>>, String, Vec<Selector>);
+
+/// every parameter group once per name it declares, in call order
+fn params(routine: &Routine) -> impl Iterator<Item = &Param> {
+    routine
+        .params
+        .iter()
+        .flat_map(|p| p.names.iter().map(move |_| p))
+}
+
+fn io_error(e: io::Error, span: Span) -> RuntimeError {
+    RuntimeError::new(RuntimeErrorKind::Io(e.to_string()), span)
+}
+
+fn input_error(e: io::Error, span: Span) -> RuntimeError {
+    RuntimeError::new(RuntimeErrorKind::Input(e.to_string()), span)
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(out: &'a mut dyn Write) -> Self {
+        Interpreter {
+            env: Rc::new(RefCell::new(Environment::new())),
+            loop_vars: vec![],
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            nesting: 0,
+            checks: Checks::default(),
+            input: Input::new(io::BufReader::new(io::stdin())),
+            out,
+        }
+    }
+
+    /// read program input from `input` instead of stdin
+    #[cfg(test)]
+    pub fn with_input(mut self, input: impl io::BufRead + 'static) -> Self {
+        self.input = Input::new(input);
+        self
+    }
+
+    /// limit how deep routine calls may nest, deeper calls are a stack overflow
+    /// error. Statements and expressions may nest `NESTING_PER_CALL` times as deep
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn run(&mut self, program: &Program) -> Result<(), RuntimeError> {
+        self.execute_block(&program.block)?;
+        self.out.flush().map_err(|e| io_error(e, Span::default()))
+    }
+
+    /// variables currently visible, for inspecting a finished run
+    #[cfg(test)]
+    pub fn env(&self) -> std::cell::Ref<'_, Environment> {
+        self.env.borrow()
+    }
+
+    fn execute_block(&mut self, block: &Block) -> Result<(), RuntimeError> {
+        for declaration in &block.declarations {
+            self.declare(declaration)?;
+        }
+        // exit leaves the block early, break and continue never get this far
+        self.execute_all(&block.body)?;
+        Ok(())
+    }
+
+    fn declare(&mut self, declaration: &Declaration) -> Result<(), RuntimeError> {
+        match declaration {
+            Declaration::Const(c) => {
+                let value = self.evaluate(&c.value)?;
+                self.env
+                    .borrow_mut()
+                    .define(&c.name.token.to_string(), value);
+            }
+            Declaration::Type(t) => {
+                let ty = self.resolve(&t.spec)?;
+                self.env
+                    .borrow_mut()
+                    .define_type(&t.name.token.to_string(), ty);
+            }
+            Declaration::Var(v) => self.declare_vars(v)?,
+            Declaration::Routine(r) => self.env.borrow_mut().define_routine(Rc::clone(r)),
+        }
+        Ok(())
+    }
+
+    /// declare variables in the innermost scope, uninitialized unless the
+    /// declaration has an initializer, which also gives the type when none is written
+    fn declare_vars(&mut self, decl: &VarDecl) -> Result<(), RuntimeError> {
+        // evaluated before declaring, so `var x := x + 1` reads the outer x
+        let init = match &decl.init {
+            Some(expr) => Some((self.evaluate(expr)?, expr)),
+            None => None,
+        };
+        let ty = match (&decl.spec, &init) {
+            (Some(spec), _) => self.resolve(spec)?,
+            (None, Some((_, expr))) => self.infer(expr)?,
+            (None, None) => unreachable!("the parser requires a type or an initializer"),
+        };
+        for name in &decl.names {
+            let name = name.token.to_string();
+            self.env.borrow_mut().declare(&name, ty.clone());
+            if let Some((value, expr)) = &init {
+                self.env
+                    .borrow_mut()
+                    .assign(&name, value.clone())
+                    .map_err(|kind| RuntimeError::new(kind, expr.span()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// the type of a variable declared as `var x := expr`, worked out from
+    /// the expression the way the type checker does, never from its value
+    fn infer(&self, expr: &Expr) -> Result<Type, RuntimeError> {
+        let env = self.env.borrow();
+        TypeChecker::with_outer(&*env).infer(expr).ok_or_else(|| {
+            let reason = format!("cannot infer a type from `{}`", expr);
+            RuntimeError::new(RuntimeErrorKind::TypeMismatch(reason), expr.span())
+        })
+    }
+
+    /// the type a type spec names, builtin or declared
+    fn resolve(&mut self, spec: &TypeSpec) -> Result<Type, RuntimeError> {
+        match spec {
+            TypeSpec::Named(tok) => {
+                let name = tok.token.to_string();
+                Type::from_name(&name)
+                    .or_else(|| self.env.borrow().find_type(&name))
+                    .ok_or_else(|| RuntimeError::new(RuntimeErrorKind::UnknownType(name), tok.span))
+            }
+            TypeSpec::Array((low, high, elem)) => {
+                let (low_value, high_value) = (self.integer(low)?, self.integer(high)?);
+                let elem = self.resolve(elem)?;
+                Type::array(low_value, high_value, elem).map_err(|reason| {
+                    RuntimeError::new(
+                        RuntimeErrorKind::TypeMismatch(reason),
+                        low.span().to(high.span()),
+                    )
+                })
+            }
+            TypeSpec::DynArray(elem) => Ok(Type::DynArray(Box::new(self.resolve(elem)?))),
+            TypeSpec::Record(decls) => {
+                let mut fields = vec![];
+                for (names, spec) in decls {
+                    let ty = self.resolve(spec)?;
+                    for name in names {
+                        fields.push((name.token.to_string(), ty.clone()));
+                    }
+                }
+                Ok(Type::Record(fields))
+            }
+        }
+    }
+
+    /// value of an expression that must be an integer, such as an index
+    fn integer(&mut self, expr: &Expr) -> Result<i64, RuntimeError> {
+        match self.evaluate(expr)? {
+            Value::Integer(i) => Ok(i),
+            v => Err(RuntimeError::new(
+                RuntimeErrorKind::TypeMismatch(format!(
+                    "expected an integer, found {}",
+                    v.type_name()
+                )),
+                expr.span(),
+            )),
+        }
+    }
+
+    /// the variable an l-value expression names and the path to the part
+    /// of it the expression picks, None for anything else
+    fn place(&mut self, expr: &Expr) -> Result<Option<(String, Vec<Selector>)>, RuntimeError> {
+        match expr {
+            Expr::Literals(SpannedToken {
+                token: Token::Identifier(name),
+                ..
+            }) => Ok(Some((name.clone(), vec![]))),
+            Expr::Index((array, index, _)) => match self.place(array)? {
+                Some((name, mut path)) => {
+                    path.push(Selector::Index(self.integer(index)?));
+                    Ok(Some((name, path)))
+                }
+                None => Ok(None),
+            },
+            Expr::Field((record, field)) => match self.place(record)? {
+                Some((name, mut path)) => {
+                    path.push(Selector::Field(field.token.to_string()));
+                    Ok(Some((name, path)))
+                }
+                None => Ok(None),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// assign to a variable or an element of one
+    fn store(&mut self, target: &Expr, value: Value) -> Result<(), RuntimeError> {
+        let error = |kind| RuntimeError::new(kind, target.span());
+        let Some((name, path)) = self.place(target)? else {
+            return Err(error(RuntimeErrorKind::NotAssignable(target.to_string())));
+        };
+        if self.loop_vars.contains(&name.to_lowercase()) {
+            return Err(error(RuntimeErrorKind::LoopVariableAssigned(name)));
+        }
+        match self.env.borrow_mut().assign_at(&name, &path, value) {
+            // with range checks off, writes outside an array go nowhere
+            Err(RuntimeErrorKind::IndexOutOfBounds(..)) if !self.checks.range => Ok(()),
+            result => result.map_err(error),
+        }
+    }
+
+    /// with range checks off, reads outside an array give the zero of its
+    /// elements, `elem` being their type if it is known
+    fn unchecked(&self, result: ValueResult, elem: Option<Type>) -> ValueResult {
+        match (result, elem) {
+            (Err(RuntimeErrorKind::IndexOutOfBounds(..)), Some(elem)) if !self.checks.range => {
+                Ok(elem.zero())
+            }
+            (result, _) => result,
+        }
+    }
+
+    fn assign(&mut self, name: &str, value: Value, span: Span) -> Result<(), RuntimeError> {
+        self.env
+            .borrow_mut()
+            .assign(name, value)
+            .map_err(|kind| RuntimeError::new(kind, span))
+    }
+
+    pub fn execute(&mut self, statement: &Statement) -> Result<Flow, RuntimeError> {
+        // switches are lexical, so a statement runs with the checks written
+        // where it starts, not those of whoever called its routine
+        self.enter(|| statement.span)?;
+        let outer = std::mem::replace(&mut self.checks, statement.checks);
+        let flow = self.execute_checked(statement);
+        self.checks = outer;
+        self.nesting -= 1;
+        flow
+    }
+
+    /// count one more nested statement or expression, too many would
+    /// overflow the native stack. The span is only worked out for the error,
+    /// that of a long chain of operators takes walking all of it
+    fn enter(&mut self, span: impl FnOnce() -> Span) -> Result<(), RuntimeError> {
+        let limit = max_nesting(self.max_depth);
+        if self.nesting >= limit {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::NestingTooDeep(limit),
+                span(),
+            ));
+        }
+        self.nesting += 1;
+        Ok(())
+    }
+
+    fn execute_checked(&mut self, statement: &Statement) -> Result<Flow, RuntimeError> {
+        match &statement.statement_type {
+            StatementType::Assign((target, expr)) => {
+                let value = self.evaluate(expr)?;
+                self.store(target, value)?;
+            }
+            StatementType::If((condition, then_branch, else_branch)) => {
+                if self.condition(condition)? {
+                    return self.execute(then_branch);
+                } else if let Some(else_branch) = else_branch {
+                    return self.execute(else_branch);
+                }
+            }
+            StatementType::While((condition, body)) => {
+                while self.condition(condition)? {
+                    match self.execute(body)? {
+                        Flow::Break => break,
+                        Flow::Exit => return Ok(Flow::Exit),
+                        _ => (),
+                    }
+                }
+            }
+            StatementType::Repeat((body, condition)) => loop {
+                match self.execute_all(body)? {
+                    Flow::Break => break,
+                    Flow::Exit => return Ok(Flow::Exit),
+                    _ => (),
+                }
+                if self.condition(condition)? {
+                    break;
+                }
+            },
+            StatementType::For((name, from, downto, to, body)) => {
+                // both bounds are evaluated once, before the first iteration
+                let from_value = self.evaluate(from)?;
+                let to_value = self.evaluate(to)?;
+                let (from, to) = match (from_value, to_value) {
+                    (Value::Integer(from), Value::Integer(to)) => (from, to),
+                    (from, to) => {
+                        return Err(RuntimeError::new(
+                            RuntimeErrorKind::TypeMismatch(format!(
+                                "for loop bounds must be integers, found {} and {}",
+                                from.type_name(),
+                                to.type_name()
+                            )),
+                            statement.span,
+                        ))
+                    }
+                };
+                let key = name.token.to_string().to_lowercase();
+                if self.loop_vars.contains(&key) {
+                    return Err(RuntimeError::new(
+                        RuntimeErrorKind::LoopVariableAssigned(name.token.to_string()),
+                        name.span,
+                    ));
+                }
+                self.loop_vars.push(key);
+                let result = self.for_loop(name, from, to, *downto, body);
+                self.loop_vars.pop();
+                return result;
+            }
+            StatementType::Compound(statements) => {
+                // a block declaring variables gets its own scope
+                if statements
+                    .iter()
+                    .any(|s| matches!(s.statement_type, StatementType::Var(_)))
+                {
+                    let enclosing = Rc::clone(&self.env);
+                    let scope = Environment::with_enclosing(Rc::clone(&enclosing));
+                    self.env = Rc::new(RefCell::new(scope));
+                    let result = self.execute_all(statements);
+                    self.env = enclosing;
+                    return result;
+                }
+                return self.execute_all(statements);
+            }
+            StatementType::With((records, body)) => {
+                let enclosing = Rc::clone(&self.env);
+                for record in records {
+                    self.open_record(record)
+                        .inspect_err(|_| self.env = Rc::clone(&enclosing))?;
+                }
+                let result = self.execute(body);
+                self.env = enclosing;
+                return result;
+            }
+            StatementType::Break => return Ok(Flow::Break),
+            StatementType::Continue => return Ok(Flow::Continue),
+            StatementType::Exit => return Ok(Flow::Exit),
+            StatementType::Var(decl) => self.declare_vars(decl)?,
+            // a function called as a statement drops its result
+            StatementType::ProcCall((name, args)) => {
+                self.call(name, args, statement.span)?;
+            }
+            StatementType::Write((args, newline)) => {
+                for arg in args {
+                    let text = self.format(arg)?;
+                    write!(self.out, "{}", text).map_err(|e| io_error(e, statement.span))?;
+                }
+                if *newline {
+                    writeln!(self.out).map_err(|e| io_error(e, statement.span))?;
+                }
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// an argument of write as it is printed
+    fn format(&mut self, arg: &WriteArg) -> Result<String, RuntimeError> {
+        let value = self.evaluate(&arg.value)?;
+        let mut format = [None, None];
+        for (part, expr) in format.iter_mut().zip([&arg.width, &arg.precision]) {
+            if let Some(expr) = expr {
+                // negative widths and precisions count as zero
+                *part = Some(self.integer(expr)?.max(0) as usize);
+            }
+        }
+        let [width, precision] = format;
+        value
+            .format(width, precision)
+            .map_err(|kind| RuntimeError::new(kind, arg.value.span()))
+    }
+
+    /// run statements in order until one breaks, continues or exits
+    fn execute_all(&mut self, statements: &[Statement]) -> Result<Flow, RuntimeError> {
+        for statement in statements {
+            let flow = self.execute(statement)?;
+            if flow != Flow::Next {
+                return Ok(flow);
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// counts from `from` to `to` inclusive, nothing runs when the range is empty
+    fn for_loop(
+        &mut self,
+        name: &SpannedToken,
+        from: i64,
+        to: i64,
+        downto: bool,
+        body: &Statement,
+    ) -> Result<Flow, RuntimeError> {
+        if (!downto && from > to) || (downto && from < to) {
+            return Ok(Flow::Next);
+        }
+        let mut i = from;
+        loop {
+            self.assign(&name.token.to_string(), Value::Integer(i), name.span)?;
+            match self.execute(body)? {
+                Flow::Break => return Ok(Flow::Next),
+                Flow::Exit => return Ok(Flow::Exit),
+                _ => (),
+            }
+            // stepping only while i != to keeps the counter from overflowing
+            if i == to {
+                return Ok(Flow::Next);
+            }
+            i = if downto { i - 1 } else { i + 1 };
+        }
+    }
+
+    /// call a user routine, functions give back their result.
+    /// `span` covers the whole call, for errors about the call itself
+    fn call(
+        &mut self,
+        name: &SpannedToken,
+        args: &[Expr],
+        span: Span,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let name_str = name.token.to_string();
+        let Some((routine, scope)) = Environment::find_routine(&self.env, &name_str) else {
+            return self.builtin(name, args, span);
+        };
+        let expected = routine.params.iter().map(|p| p.names.len()).sum();
+        if args.len() != expected {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::ArgumentCount(name_str, expected, args.len()),
+                span,
+            ));
+        }
+        if self.depth >= self.max_depth {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::StackOverflow(self.max_depth),
+                span,
+            ));
+        }
+        // arguments are evaluated in the caller's scope
+        let mut values = vec![];
+        for (param, arg) in params(&routine).zip(args) {
+            let value = match param.mode {
+                ParamMode::Var => {
+                    let ty = self.resolve(&param.spec)?;
+                    Argument::Ref(self.reference(arg, &ty)?)
+                }
+                _ => Argument::Value(self.evaluate(arg)?, arg.span()),
+            };
+            values.push(value);
+        }
+
+        let frame = Rc::new(RefCell::new(Environment::with_enclosing(scope)));
+        let caller = std::mem::replace(&mut self.env, frame);
+        let caller_loop_vars = std::mem::take(&mut self.loop_vars);
+        self.depth += 1;
+        let result = self.run_routine(&routine, values);
+        self.depth -= 1;
+        self.loop_vars = caller_loop_vars;
+        self.env = caller;
+        result
+    }
+
+    /// a scope for `with`, where each field of the record variable `record`
+    /// names that field of it
+    fn open_record(&mut self, record: &Expr) -> Result<(), RuntimeError> {
+        let error = |kind| RuntimeError::new(kind, record.span());
+        let (owner, name, path) = self.reference_any(record)?;
+        let fields = match owner.borrow().type_at(&name, &path).map_err(error)? {
+            Some(Type::Record(fields)) => fields,
+            found => {
+                let found = found.map_or("constant".to_string(), |ty| ty.to_string());
+                return Err(error(RuntimeErrorKind::TypeMismatch(format!(
+                    "with needs a record variable, found {}",
+                    found
+                ))));
+            }
+        };
+        let mut scope = Environment::with_enclosing(Rc::clone(&self.env));
+        for (field, _) in fields {
+            let mut field_path = path.clone();
+            field_path.push(Selector::Field(field.clone()));
+            scope.declare_alias(&field, Rc::clone(&owner), &name, field_path);
+        }
+        self.env = Rc::new(RefCell::new(scope));
+        Ok(())
+    }
+
+    /// procedures and functions every program can call without declaring,
+    /// a declared routine of the same name hides them
+    fn builtin(
+        &mut self,
+        name: &SpannedToken,
+        args: &[Expr],
+        span: Span,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let name_str = name.token.to_string();
+        let count = |expected| {
+            RuntimeError::new(
+                RuntimeErrorKind::ArgumentCount(name_str.clone(), expected, args.len()),
+                span,
+            )
+        };
+        let lowercase = name_str.to_lowercase();
+        match lowercase.as_str() {
+            "setlength" => {
+                let [array, length] = args else {
+                    return Err(count(2));
+                };
+                self.set_length(array, length)?;
+                Ok(None)
+            }
+            "read" | "readln" => {
+                self.read(args, lowercase == "readln", span)?;
+                Ok(None)
+            }
+            "sort" => {
+                let [array] = args else {
+                    return Err(count(1));
+                };
+                self.sort(array)?;
+                Ok(None)
+            }
+            "sqrt" | "abs" | "len" => {
+                let [arg] = args else {
+                    return Err(count(1));
+                };
+                let error = |kind| RuntimeError::new(kind, arg.span());
+                let needs = |what: &str, v: Value| {
+                    error(RuntimeErrorKind::TypeMismatch(format!(
+                        "{} needs {}, found {}",
+                        lowercase,
+                        what,
+                        v.type_name()
+                    )))
+                };
+                let result = match (lowercase.as_str(), self.evaluate(arg)?) {
+                    ("sqrt", Value::Integer(i)) if i < 0 => {
+                        Err(error(RuntimeErrorKind::NegativeRoot))
+                    }
+                    ("sqrt", Value::Real(r)) if r < 0.0 => {
+                        Err(error(RuntimeErrorKind::NegativeRoot))
+                    }
+                    ("sqrt", Value::Integer(i)) => Ok(Value::Real((i as f64).sqrt())),
+                    ("sqrt", Value::Real(r)) => Ok(Value::Real(r.sqrt())),
+                    ("abs", Value::Integer(i)) if !self.checks.overflow => {
+                        Ok(Value::Integer(i.wrapping_abs()))
+                    }
+                    ("abs", Value::Integer(i)) => i
+                        .checked_abs()
+                        .map(Value::Integer)
+                        .ok_or_else(|| error(RuntimeErrorKind::Overflow)),
+                    ("abs", Value::Real(r)) => Ok(Value::Real(r.abs())),
+                    ("len", Value::Str(s)) => Ok(Value::Integer(s.chars().count() as i64)),
+                    ("len", Value::Array(array)) => Ok(Value::Integer(array.elements.len() as i64)),
+                    ("len", v) => Err(needs("a string or an array", v)),
+                    (_, v) => Err(needs("a number", v)),
+                };
+                result.map(Some)
+            }
+            _ => Err(RuntimeError::new(
+                RuntimeErrorKind::UnknownProcedure(name_str),
+                name.span,
+            )),
+        }
+    }
+
+    /// `SetLength(a, n)`, resizes a dynamic array keeping its first elements
+    fn set_length(&mut self, array: &Expr, length: &Expr) -> Result<(), RuntimeError> {
+        let error = |kind| RuntimeError::new(kind, array.span());
+        let (owner, name, path) = self.reference_any(array)?;
+        let elem = match owner.borrow().type_at(&name, &path).map_err(error)? {
+            Some(Type::DynArray(elem)) => elem,
+            found => {
+                let found = found.map_or("constant".to_string(), |ty| ty.to_string());
+                return Err(error(RuntimeErrorKind::TypeMismatch(format!(
+                    "SetLength needs a dynamic array, found {}",
+                    found
+                ))));
+            }
+        };
+        let length_value = self.integer(length)?.max(0);
+        if length_value > 0 {
+            Type::array(0, length_value - 1, (*elem).clone()).map_err(|reason| {
+                RuntimeError::new(RuntimeErrorKind::TypeMismatch(reason), length.span())
+            })?;
+        }
+        let length = length_value as usize;
+        let mut owner = owner.borrow_mut();
+        let Value::Array(mut resized) = owner.get_at(&name, &path).map_err(error)? else {
+            unreachable!("dynamic arrays always hold an array");
+        };
+        resized.elements.resize(length, elem.zero());
+        owner
+            .assign_at(&name, &path, Value::Array(resized))
+            .map_err(error)
+    }
+
+    /// `read` and `readln`, each argument is a variable to read a value of its
+    /// type into, `readln` then skips the rest of the line
+    fn read(&mut self, args: &[Expr], line: bool, span: Span) -> Result<(), RuntimeError> {
+        for arg in args {
+            let error = |kind| RuntimeError::new(kind, arg.span());
+            let (owner, name, path) = self.reference_any(arg)?;
+            let ty = owner.borrow().type_at(&name, &path).map_err(error)?;
+            let Some(ty) = ty else {
+                return Err(error(RuntimeErrorKind::AssignToConstant(name)));
+            };
+            let value = self.read_value(&ty).map_err(error)?;
+            owner
+                .borrow_mut()
+                .assign_at(&name, &path, value)
+                .map_err(error)?;
+        }
+        if line {
+            self.input.skip_line().map_err(|e| input_error(e, span))?;
+        }
+        Ok(())
+    }
+
+    /// the next value of type `ty` in the input
+    fn read_value(&mut self, ty: &Type) -> Result<Value, RuntimeErrorKind> {
+        let failed = |e: io::Error| RuntimeErrorKind::Input(e.to_string());
+        let end = || RuntimeErrorKind::Input("unexpected end of input".to_string());
+        match ty {
+            Type::Str => Ok(Value::Str(self.input.rest_of_line().map_err(failed)?)),
+            Type::Char => self
+                .input
+                .char()
+                .map_err(failed)?
+                .map(Value::Char)
+                .ok_or_else(end),
+            Type::Integer | Type::Real => {
+                let word = self.input.word().map_err(failed)?.ok_or_else(end)?;
+                let value = match ty {
+                    Type::Integer => word.parse().ok().map(Value::Integer),
+                    _ => word.parse().ok().map(Value::Real),
+                };
+                value.ok_or_else(|| {
+                    RuntimeErrorKind::Input(format!("expected {}, found `{}`", ty, word))
+                })
+            }
+            ty => Err(RuntimeErrorKind::TypeMismatch(format!(
+                "cannot read a {}",
+                ty
+            ))),
+        }
+    }
+
+    /// `sort(a)`, orders the elements of an array variable in place
+    fn sort(&mut self, array: &Expr) -> Result<(), RuntimeError> {
+        let error = |kind| RuntimeError::new(kind, array.span());
+        let (owner, name, path) = self.reference_any(array)?;
+        match owner.borrow().type_at(&name, &path).map_err(error)? {
+            Some(Type::Array((_, _, elem)) | Type::DynArray(elem)) if elem.is_scalar() => (),
+            found => {
+                let found = found.map_or("constant".to_string(), |ty| ty.to_string());
+                return Err(error(RuntimeErrorKind::TypeMismatch(format!(
+                    "sort needs an array of scalars, found {}",
+                    found
+                ))));
+            }
+        }
+        let mut owner = owner.borrow_mut();
+        let Value::Array(mut sorted) = owner.get_at(&name, &path).map_err(error)? else {
+            unreachable!("array variables always hold an array");
+        };
+        // elements share a scalar type, so only NaN leaves a pair unordered
+        sorted
+            .elements
+            .sort_by(|a, b| a.compare(b).unwrap_or(Ordering::Equal));
+        owner
+            .assign_at(&name, &path, Value::Array(sorted))
+            .map_err(error)
+    }
+
+    /// the variable, or part of one, an argument names, with the scope
+    /// declaring the variable
+    fn reference_any(&mut self, arg: &Expr) -> Result<Reference, RuntimeError> {
+        let error = |kind| RuntimeError::new(kind, arg.span());
+        let Some((name, path)) = self.place(arg)? else {
+            return Err(error(RuntimeErrorKind::NotAssignable(arg.to_string())));
+        };
+        if self.loop_vars.contains(&name.to_lowercase()) {
+            return Err(error(RuntimeErrorKind::LoopVariableAssigned(name)));
+        }
+        let owner = Environment::owner(&self.env, &name)
+            .ok_or_else(|| error(RuntimeErrorKind::UndeclaredVariable(name.clone())))?;
+        Ok((owner, name, path))
+    }
+
+    /// the variable a `var` parameter binds to, which must be a variable of
+    /// exactly the parameter's type
+    fn reference(&mut self, arg: &Expr, ty: &Type) -> Result<Reference, RuntimeError> {
+        let error = |kind| RuntimeError::new(kind, arg.span());
+        let (owner, name, path) = self.reference_any(arg)?;
+        let found = owner.borrow().type_at(&name, &path).map_err(error)?;
+        match found {
+            Some(found) if found == *ty => Ok((owner, name, path)),
+            Some(found) => Err(error(RuntimeErrorKind::TypeMismatch(format!(
+                "var parameter needs a {} variable, found {}",
+                ty, found
+            )))),
+            None => Err(error(RuntimeErrorKind::NotAssignable(name.clone()))),
+        }
+    }
+
+    /// bind the parameters and run the body in the routine's fresh scope
+    fn run_routine(
+        &mut self,
+        routine: &Routine,
+        values: Vec<Argument>,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let names = routine.params.iter().flat_map(|p| p.names.iter());
+        for ((param, name), value) in params(routine).zip(names).zip(values) {
+            let name = name.token.to_string();
+            match value {
+                Argument::Ref((owner, target, path)) => self
+                    .env
+                    .borrow_mut()
+                    .declare_alias(&name, owner, &target, path),
+                Argument::Value(value, span) => {
+                    let ty = self.resolve(&param.spec)?;
+                    if param.mode == ParamMode::Const {
+                        let value = ty
+                            .coerce(value)
+                            .map_err(|kind| RuntimeError::new(kind, span))?;
+                        self.env.borrow_mut().define(&name, value);
+                    } else {
+                        self.env.borrow_mut().declare(&name, ty);
+                        self.assign(&name, value, span)?;
+                    }
+                }
+            }
+        }
+        // a function returns whatever was last assigned to its name
+        let name = routine.name.token.to_string();
+        if let Some(spec) = &routine.return_type {
+            let ty = self.resolve(spec)?;
+            self.env.borrow_mut().declare(&name, ty);
+        }
+        self.execute_block(&routine.block)?;
+        match &routine.return_type {
+            Some(_) => match self.env.borrow().get(&name) {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => Err(RuntimeError::new(
+                    RuntimeErrorKind::NoResult(name.clone()),
+                    routine.name.span,
+                )),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn condition(&mut self, expr: &Expr) -> Result<bool, RuntimeError> {
+        match self.evaluate(expr)? {
+            Value::Boolean(b) => Ok(b),
+            v => Err(RuntimeError::new(
+                RuntimeErrorKind::TypeMismatch(format!(
+                    "condition must be a boolean, found {}",
+                    v.type_name()
+                )),
+                expr.span(),
+            )),
+        }
+    }
+
+    pub fn evaluate(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        self.enter(|| expr.span())?;
+        let value = self.evaluate_nested(expr);
+        self.nesting -= 1;
+        value
+    }
+
+    fn evaluate_nested(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        let result = match expr {
+            Expr::Unary((ops, operand)) => {
+                let value = self.evaluate(operand)?;
+                match ops.token {
+                    // with overflow checks off, integers wrap around
+                    Token::Minus => match value {
+                        Value::Integer(i) if !self.checks.overflow => {
+                            Ok(Value::Integer(i.wrapping_neg()))
+                        }
+                        value => -value,
+                    },
+                    Token::Not => !value,
+                    ref op => Err(RuntimeErrorKind::Unsupported(format!("operator `{}`", op))),
+                }
+            }
+
+            Expr::Binary((lhs, ops, rhs)) if matches!(ops.token, Token::And | Token::Or) => {
+                let lhs = self.evaluate(lhs)?;
+                // short circuit: the right side of a boolean and/or may never run
+                match (&ops.token, &lhs) {
+                    (Token::And, Value::Boolean(false)) => return Ok(lhs),
+                    (Token::Or, Value::Boolean(true)) => return Ok(lhs),
+                    _ => (),
+                }
+                let rhs = self.evaluate(rhs)?;
+                if ops.token == Token::And {
+                    lhs & rhs
+                } else {
+                    lhs | rhs
+                }
+            }
+
+            Expr::Binary((lhs, ops, rhs)) => {
+                let lhs = self.evaluate(lhs)?;
+                let rhs = self.evaluate(rhs)?;
+                let wrapped = match self.checks.overflow {
+                    true => None,
+                    false => Value::wrapping(&ops.token, &lhs, &rhs),
+                };
+                if let Some(wrapped) = wrapped {
+                    return Ok(wrapped);
+                }
+                match ops.token {
+                    Token::Plus => lhs + rhs,
+                    Token::Minus => lhs - rhs,
+
+                    Token::Mul => lhs * rhs,
+                    Token::Slash => lhs / rhs,
+                    Token::Div => lhs.int_div(rhs),
+                    Token::Mod => lhs % rhs,
+
+                    Token::Pow => lhs.pow_value(rhs),
+
+                    Token::Eq => lhs
+                        .compare(&rhs)
+                        .map(|o| Value::Boolean(o == Ordering::Equal)),
+                    Token::Neq => lhs
+                        .compare(&rhs)
+                        .map(|o| Value::Boolean(o != Ordering::Equal)),
+                    Token::Great => lhs.compare(&rhs).map(|o| Value::Boolean(o.is_gt())),
+                    Token::GreatEq => lhs.compare(&rhs).map(|o| Value::Boolean(o.is_ge())),
+                    Token::Less => lhs.compare(&rhs).map(|o| Value::Boolean(o.is_lt())),
+                    Token::LessEq => lhs.compare(&rhs).map(|o| Value::Boolean(o.is_le())),
+                    ref op => Err(RuntimeErrorKind::Unsupported(format!("operator `{}`", op))),
+                }
+            }
+            Expr::Literals(value) => match &value.token {
+                Token::Identifier(name) => {
+                    let found = self.env.borrow().get(name);
+                    match found {
+                        // a function without parameters can be called without parentheses
+                        Err(RuntimeErrorKind::UndeclaredVariable(_))
+                            if Environment::find_routine(&self.env, name).is_some() =>
+                        {
+                            return self.function_call(value, &[], value.span);
+                        }
+                        found => found,
+                    }
+                }
+                tok => Value::from_literal(tok)
+                    .ok_or_else(|| RuntimeErrorKind::Unsupported(format!("literal `{}`", tok))),
+            },
+            Expr::Grouping((expr, _)) => return self.evaluate(expr),
+            Expr::Call((name, args, span)) => return self.function_call(name, args, *span),
+            Expr::Index((array, index, _)) => match self.place(expr)? {
+                // elements of variables are read in place, not copied out with the array
+                Some((name, path)) => {
+                    let env = self.env.borrow();
+                    let elem = env.type_at(&name, &path).ok().flatten();
+                    self.unchecked(env.get_at(&name, &path), elem)
+                }
+                None => {
+                    let array = self.evaluate(array)?;
+                    let index = self.integer(index)?;
+                    let elem = match &array {
+                        Value::Array(a) => a.elements.first().and_then(Type::of),
+                        _ => None,
+                    };
+                    self.unchecked(array.select(&Selector::Index(index)).cloned(), elem)
+                }
+            },
+            Expr::Field((record, field)) => match self.place(expr)? {
+                Some((name, path)) => self.env.borrow().get_at(&name, &path),
+                None => {
+                    let record = self.evaluate(record)?;
+                    record
+                        .select(&Selector::Field(field.token.to_string()))
+                        .cloned()
+                }
+            },
+        };
+        result.map_err(|kind| RuntimeError::new(kind, expr.span()))
+    }
+
+    /// a call used as an expression, which must produce a value
+    fn function_call(
+        &mut self,
+        name: &SpannedToken,
+        args: &[Expr],
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        match self.call(name, args, span)? {
+            Some(value) => Ok(value),
+            None => Err(RuntimeError::new(
+                RuntimeErrorKind::TypeMismatch(format!(
+                    "procedure `{}` does not return a value",
+                    name.token
+                )),
+                span,
+            )),
+        }
+    }
+}
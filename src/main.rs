@@ -1,40 +1,140 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io, process, thread};
+
+use error::DuYError;
+use interpreter::{Interpreter, DEFAULT_MAX_DEPTH, NESTING_PER_CALL};
 use parser::Parser;
 use tokenizer::Tokenizer;
+use typeck::TypeChecker;
 
 // mod error;
+mod environment;
 mod error;
 mod helper;
+mod input;
+mod interpreter;
 mod parser;
 mod test;
 mod tokenizer;
+mod typeck;
 mod types;
 
+// native stack one nested statement or expression needs, the worst
+// measured in debug builds is about 18 KiB, for a parenthesized
+// expression in the parser
+const STACK_PER_FRAME: usize = 24 * 1024;
+
+/// a stack deep enough to parse, check and run everything the call depth
+/// limit allows, None when that does not fit in a usize
+fn stack_size(max_depth: usize) -> Option<usize> {
+    max_depth
+        .checked_mul(NESTING_PER_CALL)
+        .and_then(|frames| frames.checked_add(64))
+        .and_then(|frames| frames.checked_mul(STACK_PER_FRAME))
+}
+
 fn main() {
-    test_statements();
+    // usage: pascalpp [--max-depth N] [--tokens] [file.pas]
+    let mut path = String::from("test.pas");
+    let mut max_depth = DEFAULT_MAX_DEPTH;
+    let mut tokens = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--max-depth" {
+            max_depth = match args.next().map(|n| n.parse()) {
+                Some(Ok(n)) => n,
+                _ => {
+                    eprintln!("--max-depth needs a number");
+                    process::exit(1);
+                }
+            };
+        } else if arg == "--tokens" {
+            tokens = true;
+        } else {
+            path = arg;
+        }
+    }
+    let src = match fs::read_to_string(&path) {
+        Ok(src) => src,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    // {$I} includes are found next to the file including them
+    let dir = Path::new(&path)
+        .parent()
+        .map(PathBuf::from)
+        .unwrap_or_default();
+    if tokens {
+        if let Err(e) = print_tokens(&src, dir) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    // recursion in the program is recursion in the interpreter, so give it
+    // a stack deep enough for the nesting the call depth limit allows
+    let Some(stack) = stack_size(max_depth) else {
+        eprintln!("--max-depth {} is too large", max_depth);
+        process::exit(1);
+    };
+    let interpreter = match thread::Builder::new()
+        .stack_size(stack)
+        .spawn(move || run(&src, dir, max_depth))
+    {
+        Ok(interpreter) => interpreter,
+        Err(e) => {
+            eprintln!(
+                "cannot reserve the stack for --max-depth {}: {}",
+                max_depth, e
+            );
+            process::exit(1);
+        }
+    };
+    if let Err(e) = interpreter.join().expect("interpreter thread panicked") {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
 }
 
-fn test_evaluation_expression() {
-    let test_str = String::from("(1+2)*3+5*6+8^3/23+1312");
-    // let test_str = String::from("--1");
-    // let test_str = String::from("(1+2)*3");
-
-    let mut tokenizer = Tokenizer::new(&test_str);
-    let result_vec = tokenizer.tokenize_full_src().unwrap();
-    // println!("{:?}", result_vec);
-
-    let mut parser = Parser::new(result_vec);
-    let expr = parser.expression();
-    // print!("{}", expr);
-    let evaluated = expr.eval();
-    println!("{}", evaluated);
-    // print!("{}", Token::While);
+/// every token of the source with where it starts, whitespace and comments included
+fn print_tokens(src: &str, dir: PathBuf) -> Result<(), DuYError> {
+    let mut out = io::stdout().lock();
+    for tok in Tokenizer::new(src).with_dir(dir).with_trivia() {
+        let tok = tok?;
+        // a closed pipe, as with `| head`, just ends the listing
+        if writeln!(out, "{} {:?}", tok.span, tok.token).is_err() {
+            break;
+        }
+    }
+    Ok(())
 }
-fn test_statements() {
-    let test_str = "write('');";
-    let mut tokenizer = Tokenizer::new(&test_str);
-    let result_vec = tokenizer.tokenize_full_src().unwrap();
-    println!("{:?}", result_vec);
-    let mut parser = Parser::new(result_vec);
-    let statement = parser.statement();
-    println!("{:?}", statement);
+
+fn run(src: &str, dir: PathBuf, max_depth: usize) -> Result<(), DuYError> {
+    let mut tokenizer = Tokenizer::new(src).with_dir(dir);
+    let result_vec = tokenizer.tokenize_full_src()?;
+
+    let mut parser = Parser::new(result_vec).with_max_depth(max_depth);
+    let (program, errors) = parser.program();
+    if !errors.is_empty() {
+        for e in &errors {
+            eprintln!("{}", e);
+        }
+        process::exit(1);
+    }
+    // nothing runs while the program has type errors
+    let errors = TypeChecker::new().with_max_depth(max_depth).check(&program);
+    if !errors.is_empty() {
+        for e in &errors {
+            eprintln!("{}", e);
+        }
+        process::exit(1);
+    }
+
+    let mut stdout = io::stdout();
+    let mut interpreter = Interpreter::new(&mut stdout).with_max_depth(max_depth);
+    interpreter.run(&program)?;
+    Ok(())
 }
@@ -1,231 +1,899 @@
-use crate::types::{Expr, Statement, StatementType, Token};
+use std::rc::Rc;
+
+use crate::error::{ParseError, ParseErrorKind};
+use crate::interpreter::{max_nesting, DEFAULT_MAX_DEPTH};
+use crate::types::{
+    Block, Checks, ConstDecl, Declaration, Expr, Param, ParamMode, Program, Routine, Span,
+    SpannedToken, Statement, StatementType, Token, TypeDecl, TypeSpec, VarDecl, WriteArg,
+};
 // ```Java
-// expression     → equality ;
-// boolean        ->  equality && equality  , left associate
-// equality       → comparison ( ( "!=" | "==" ) comparison )* ;
+// program        → ( "program" IDENT ";" )? block "." ;
+// block          → declaration* "begin" statement ( ";" statement )* "end" ;
+// declaration    → "const" ( IDENT "=" expression ";" )+
+//                | "type" ( IDENT "=" type ";" )+
+//                | "var" ( var_decl ";" )+
+//                | ( "function" | "procedure" ) IDENT params? ( ":" type )? ";" block ";" ;
+// params         → "(" param ( ";" param )* ")" ;
+// param          → ( "var" | "const" )? IDENT ( "," IDENT )* ":" type ;
+// type           → IDENT
+//                | "array" ( "[" range ( "," range )* "]" )? "of" type
+//                | "record" ( IDENT ( "," IDENT )* ":" type ";"? )* "end" ;
+// range          → expression ".." expression ;
+// var_decl       → IDENT ( "," IDENT )* ( ":" type )? ( ( ":=" | "=" ) expression )? ;
+// statement      → IDENT selector* ":=" expression
+//                | IDENT arguments?
+//                | "begin" statement ( ";" statement )* "end"
+//                | "if" expression "then" statement ( "else" statement )?
+//                | "while" expression "do" statement
+//                | "repeat" statement ( ";" statement )* "until" expression
+//                | "for" IDENT ":=" expression ( "to" | "downto" ) expression "do" statement
+//                | "with" expression ( "," expression )* "do" statement
+//                | "break" | "continue" | "exit"
+//                | "var" var_decl
+//                | ( "read" | "sort" | "sqrt" | "abs" | "len" ) arguments?
+//                | ( "write" | "writeln" ) ( "(" ( write_arg ( "," write_arg )* )? ")" )? ;
+// write_arg      → expression ( ":" expression ( ":" expression )? )? ;
+//
+// expression     → or ;
+// or             → and ( "or" and )* ;
+// and            → equality ( "and" equality )* ;
+// equality       → comparison ( ( "<>" | "=" ) comparison )* ;
 // comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
 // term           → factor ( ( "-" | "+" ) factor )* ;
-// factor         → power ( ( "/" | "*" ) power )* ;
+// factor         → power ( ( "/" | "*" | "div" | "mod" ) power )* ;
 // power          -> unary ^ unary
-// unary          → ( "!" | "-" ) unary
-//                | primary ;
-// primary        → NUMBER | STRING | "true" | "false" | "nil"
+// unary          → ( "not" | "-" ) unary
+//                | primary selector* ;
+// selector       → "[" expression ( "," expression )* "]" | "." IDENT ;
+// primary        → NUMBER | STRING | "true" | "false" | "endl"
+//                | IDENT arguments?
+//                | ( "sqrt" | "abs" | "len" ) arguments
 //                | "(" expression ")" ;
+// arguments      → "(" ( expression ( "," expression )* )? ")" ;
 // ```
 
 pub struct Parser {
-    src: Vec<Token>,
+    src: Vec<SpannedToken>,
+    /// the checks switched on at each token of `src`
+    checks: Vec<Checks>,
     current: usize,
+    errors: Vec<ParseError>,
+    /// how many loops enclose the current statement, break and continue need one
+    loop_depth: usize,
+    /// statements, expressions, types and routines currently being parsed
+    nesting: usize,
+    max_nesting: usize,
 }
 
 impl Parser {
-    pub fn new(src: Vec<Token>) -> Self {
-        Parser { src, current: 0 }
+    pub fn new(src: Vec<SpannedToken>) -> Self {
+        // switches only matter to the statements that follow them
+        let mut tokens = vec![];
+        let mut checks = vec![];
+        let mut current = Checks::default();
+        for tok in src {
+            match tok.token {
+                Token::Checks(switched) => current = switched,
+                _ => {
+                    tokens.push(tok);
+                    checks.push(current);
+                }
+            }
+        }
+        Parser {
+            src: tokens,
+            checks,
+            current: 0,
+            errors: vec![],
+            loop_depth: 0,
+            nesting: 0,
+            max_nesting: max_nesting(DEFAULT_MAX_DEPTH),
+        }
+    }
+
+    /// refuse programs nesting deeper than the interpreter would run them
+    /// with calls nesting at most `max_depth` deep
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_nesting = max_nesting(max_depth);
+        self
     }
 
-    fn is_at_end(&self) -> bool {
-        self.current == self.src.len() - 1
+    fn get_current(&self) -> Token {
+        self.src[self.current].token.to_owned()
     }
 
-    fn get_current<'a>(&self) -> Token {
+    fn get_current_spanned(&self) -> SpannedToken {
         self.src[self.current].to_owned()
     }
 
+    fn current_span(&self) -> Span {
+        self.src[self.current].span
+    }
+
+    /// from `start` up to the end of the last consumed token
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.src[self.current.saturating_sub(1)].span)
+    }
+
     fn move_on(&mut self, step: usize) {
         if self.current + step < self.src.len() {
             self.current += step;
         }
     }
 
-    fn match_types_vec(x: &Token, inp: &Vec<Token>) -> bool {
-        for token in inp {
-            if x == token {
-                return true;
+    fn match_types_vec(x: &Token, inp: &[Token]) -> bool {
+        inp.contains(x)
+    }
+
+    /// same kind of token, literal values are not compared
+    fn check(&self, tok: &Token) -> bool {
+        std::mem::discriminant(&self.get_current()) == std::mem::discriminant(tok)
+    }
+
+    /// error about the current token
+    fn error(&self, kind: ParseErrorKind, expected: Vec<Token>) -> ParseError {
+        ParseError::new(kind, expected, self.get_current(), self.current_span())
+    }
+
+    /// consume the current token if it is `expected`, otherwise report `kind`
+    fn consume(
+        &mut self,
+        expected: Token,
+        kind: ParseErrorKind,
+    ) -> Result<SpannedToken, ParseError> {
+        if self.check(&expected) {
+            let tok = self.get_current_spanned();
+            self.move_on(1);
+            Ok(tok)
+        } else {
+            Err(self.error(kind, vec![expected]))
+        }
+    }
+
+    fn identifier(&mut self) -> Result<SpannedToken, ParseError> {
+        self.consume(
+            Token::Identifier(String::new()),
+            ParseErrorKind::UnexpectedToken,
+        )
+    }
+
+    /// panic mode: skip tokens until a point where a new statement
+    /// or declaration can start
+    fn synchronize(&mut self) {
+        loop {
+            match self.get_current() {
+                Token::EOF => return,
+                Token::SemiColon => {
+                    self.move_on(1);
+                    return;
+                }
+                Token::Begin
+                | Token::End
+                | Token::Program
+                | Token::Const
+                | Token::Type
+                | Token::Var
+                | Token::Procedure
+                | Token::If
+                | Token::While
+                | Token::Repeat
+                | Token::For
+                | Token::With
+                | Token::Function
+                | Token::Write
+                | Token::Read => return,
+                _ => self.move_on(1),
             }
         }
-        false
     }
 
-    ///create a list of statements from token
-    //         let statements:Vec<Statement>;
-    //     fn parse_to_statements(&mut self)->Vec<Statement>{
-    //     while(!self.is_at_end()){
-    //         statements.push(self.statement());
-    //         self.move_on();
-    //         }
-    // statements
-    //     }
-    fn peek(&self, step: usize) -> Token {
-        let target_index = self.current + step;
-        if target_index > self.src.len() {
-            Token::EOF
-        } else {
-            self.src[target_index].to_owned()
+    /// parse one level deeper, too deep would overflow the native stack
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Parser) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.nesting >= self.max_nesting {
+            let kind = ParseErrorKind::NestingTooDeep(self.max_nesting);
+            return Err(self.error(kind, vec![]));
         }
+        self.nesting += 1;
+        let result = parse(self);
+        self.nesting -= 1;
+        result
+    }
+
+    /// record a syntax error and skip to where parsing can carry on
+    fn report(&mut self, e: ParseError) {
+        self.errors.push(e);
+        self.synchronize();
     }
 
-    ///validate if the next tokens (from current) match the input Vec token
-    fn match_tok_in_order(&self, toks: Vec<Token>) -> bool {
-        let size = toks.len();
-        for i in 0..size {
-            if std::mem::discriminant(&self.peek(i)) != std::mem::discriminant(&toks[i]) {
-                return false;
+    /// parse a whole program, collecting every syntax error on the way.
+    /// The returned program leaves out the parts that failed to parse
+    pub fn program(&mut self) -> (Program, Vec<ParseError>) {
+        // the name in the header is not used by anything
+        if self.get_current() == Token::Program {
+            if let Err(e) = self.program_header() {
+                self.report(e);
             }
         }
-        true
+        let block = self.block();
+        match self.consume(Token::Dot, ParseErrorKind::UnexpectedToken) {
+            Ok(_) if !self.check(&Token::EOF) => self
+                .errors
+                .push(self.error(ParseErrorKind::UnexpectedToken, vec![Token::EOF])),
+            Ok(_) => (),
+            Err(e) => self.errors.push(e),
+        }
+        // recovery from a part nested too deep only finds more of it
+        let too_deep = self
+            .errors
+            .iter()
+            .position(|e| matches!(e.kind, ParseErrorKind::NestingTooDeep(_)));
+        if let Some(first) = too_deep {
+            self.errors.truncate(first + 1);
+        }
+        (Program { block }, std::mem::take(&mut self.errors))
     }
-    pub fn statement(&mut self) -> Statement {
-        let tok = self.get_current();
-        match tok {
-            Token::Write => {
-                if !self.match_tok_in_order(vec![
-                    Token::Write,
-                    Token::OParen,
-                    Token::StringLiteral(String::from("")),
-                    Token::CParen,
-                    Token::SemiColon,
-                ]) {
-                    panic!("Invalid print statement");
-                } else {
-                    self.move_on(2);
-                    let expr = self.expression();
-                    Statement::new(expr, StatementType::ProcCall(Token::Write))
+
+    fn program_header(&mut self) -> Result<(), ParseError> {
+        self.move_on(1);
+        self.identifier()?;
+        self.consume(Token::SemiColon, ParseErrorKind::MissingSemiColon)?;
+        Ok(())
+    }
+
+    fn block(&mut self) -> Block {
+        let declarations = self.declarations();
+        if let Err(e) = self.consume(Token::Begin, ParseErrorKind::UnexpectedToken) {
+            self.report(e);
+        }
+        let body = self.statement_list(&[Token::End]);
+        if let Err(e) = self.consume(Token::End, ParseErrorKind::UnexpectedToken) {
+            self.errors.push(e);
+        }
+        Block { declarations, body }
+    }
+
+    fn declarations(&mut self) -> Vec<Declaration> {
+        let mut declarations = vec![];
+        loop {
+            match self.get_current() {
+                Token::Const => declarations.extend(
+                    self.section(Parser::const_decl)
+                        .into_iter()
+                        .map(Declaration::Const),
+                ),
+                Token::Type => declarations.extend(
+                    self.section(Parser::type_decl)
+                        .into_iter()
+                        .map(Declaration::Type),
+                ),
+                Token::Var => declarations.extend(
+                    self.section(Parser::var_decl)
+                        .into_iter()
+                        .map(Declaration::Var),
+                ),
+                Token::Function | Token::Procedure => match self.nested(Parser::routine) {
+                    Ok(routine) => declarations.push(Declaration::Routine(Rc::new(routine))),
+                    Err(e) => self.report(e),
+                },
+                _ => return declarations,
+            }
+        }
+    }
+
+    /// `keyword item item ...` where every item starts with an identifier
+    fn section<T>(&mut self, item: fn(&mut Parser) -> Result<T, ParseError>) -> Vec<T> {
+        self.move_on(1); // the section keyword
+        let mut items = vec![];
+        loop {
+            match item(self) {
+                Ok(i) => items.push(i),
+                Err(e) => self.report(e),
+            }
+            if !self.check(&Token::Identifier(String::new())) {
+                return items;
+            }
+        }
+    }
+
+    fn const_decl(&mut self) -> Result<ConstDecl, ParseError> {
+        let name = self.identifier()?;
+        self.consume(Token::Eq, ParseErrorKind::UnexpectedToken)?;
+        let value = self.expression()?;
+        self.consume(Token::SemiColon, ParseErrorKind::MissingSemiColon)?;
+        Ok(ConstDecl { name, value })
+    }
+
+    fn type_decl(&mut self) -> Result<TypeDecl, ParseError> {
+        let name = self.identifier()?;
+        self.consume(Token::Eq, ParseErrorKind::UnexpectedToken)?;
+        let spec = self.type_spec()?;
+        self.consume(Token::SemiColon, ParseErrorKind::MissingSemiColon)?;
+        Ok(TypeDecl { name, spec })
+    }
+
+    fn var_decl(&mut self) -> Result<VarDecl, ParseError> {
+        let decl = self.var_names_and_type()?;
+        self.consume(Token::SemiColon, ParseErrorKind::MissingSemiColon)?;
+        Ok(decl)
+    }
+
+    // shared by var sections and var statements, which leave the `;` to statement_list
+    fn var_names_and_type(&mut self) -> Result<VarDecl, ParseError> {
+        let names = self.identifier_list()?;
+        let spec = match self.get_current() {
+            Token::Colon => {
+                self.move_on(1);
+                Some(self.type_spec()?)
+            }
+            _ => None,
+        };
+        let init = match self.get_current() {
+            Token::Assign | Token::Eq => {
+                self.move_on(1);
+                Some(self.expression()?)
+            }
+            // without a type, the initializer is what the type is inferred from
+            _ if spec.is_none() => {
+                return Err(self.error(
+                    ParseErrorKind::UnexpectedToken,
+                    vec![Token::Colon, Token::Assign],
+                ))
+            }
+            _ => None,
+        };
+        Ok(VarDecl { names, spec, init })
+    }
+
+    fn identifier_list(&mut self) -> Result<Vec<SpannedToken>, ParseError> {
+        let mut names = vec![self.identifier()?];
+        while self.get_current() == Token::Comma {
+            self.move_on(1);
+            names.push(self.identifier()?);
+        }
+        Ok(names)
+    }
+
+    fn type_spec(&mut self) -> Result<TypeSpec, ParseError> {
+        self.nested(Parser::type_spec_nested)
+    }
+
+    fn type_spec_nested(&mut self) -> Result<TypeSpec, ParseError> {
+        match self.get_current() {
+            Token::Array => (),
+            Token::Record => return self.record(),
+            _ => return Ok(TypeSpec::Named(self.identifier()?)),
+        }
+        self.move_on(1);
+        if self.get_current() != Token::OBracket {
+            self.consume(Token::Of, ParseErrorKind::UnexpectedToken)?;
+            return Ok(TypeSpec::DynArray(Box::new(self.type_spec()?)));
+        }
+        self.move_on(1);
+        let mut ranges = vec![self.range()?];
+        while self.get_current() == Token::Comma {
+            self.move_on(1);
+            ranges.push(self.range()?);
+        }
+        self.consume(Token::CBracket, ParseErrorKind::UnexpectedToken)?;
+        self.consume(Token::Of, ParseErrorKind::UnexpectedToken)?;
+        // array[a..b, c..d] of T is array[a..b] of array[c..d] of T
+        let mut spec = self.type_spec()?;
+        for (low, high) in ranges.into_iter().rev() {
+            spec = TypeSpec::Array((Box::new(low), Box::new(high), Box::new(spec)));
+        }
+        Ok(spec)
+    }
+
+    fn record(&mut self) -> Result<TypeSpec, ParseError> {
+        self.move_on(1);
+        let mut fields = vec![];
+        while self.get_current() != Token::End {
+            let names = self.identifier_list()?;
+            self.consume(Token::Colon, ParseErrorKind::UnexpectedToken)?;
+            fields.push((names, self.type_spec()?));
+            // the `;` after the last field is optional
+            if self.get_current() != Token::End {
+                self.consume(Token::SemiColon, ParseErrorKind::MissingSemiColon)?;
+            }
+        }
+        self.move_on(1);
+        Ok(TypeSpec::Record(fields))
+    }
+
+    fn range(&mut self) -> Result<(Expr, Expr), ParseError> {
+        let low = self.expression()?;
+        self.consume(Token::DotDot, ParseErrorKind::UnexpectedToken)?;
+        Ok((low, self.expression()?))
+    }
+
+    fn routine(&mut self) -> Result<Routine, ParseError> {
+        let is_function = self.get_current() == Token::Function;
+        self.move_on(1);
+        let name = self.identifier()?;
+
+        let mut params = vec![];
+        if self.get_current() == Token::OParen {
+            self.move_on(1);
+            if self.get_current() != Token::CParen {
+                params.push(self.param()?);
+                while self.get_current() == Token::SemiColon {
+                    self.move_on(1);
+                    params.push(self.param()?);
                 }
             }
-            _ => panic!("Invalid print statement"),
+            self.consume(Token::CParen, ParseErrorKind::MissingCloseParen)?;
+        }
+
+        let mut return_type = None;
+        if is_function {
+            self.consume(Token::Colon, ParseErrorKind::UnexpectedToken)?;
+            return_type = Some(self.type_spec()?);
         }
+        self.consume(Token::SemiColon, ParseErrorKind::MissingSemiColon)?;
+
+        let block = self.block();
+        self.consume(Token::SemiColon, ParseErrorKind::MissingSemiColon)?;
+        Ok(Routine {
+            name,
+            params,
+            return_type,
+            block,
+        })
     }
 
-    // Recursive Descent Grammar
-    pub fn expression(&mut self) -> Expr {
-        *self.equality()
+    fn param(&mut self) -> Result<Param, ParseError> {
+        let mode = match self.get_current() {
+            Token::Var => ParamMode::Var,
+            Token::Const => ParamMode::Const,
+            _ => ParamMode::Value,
+        };
+        if mode != ParamMode::Value {
+            self.move_on(1);
+        }
+        let names = self.identifier_list()?;
+        self.consume(Token::Colon, ParseErrorKind::UnexpectedToken)?;
+        let spec = self.type_spec()?;
+        Ok(Param { mode, names, spec })
+    }
+
+    /// the statement after `do`, where break and continue are allowed
+    fn loop_body(&mut self) -> Result<Statement, ParseError> {
+        self.loop_depth += 1;
+        let body = self.statement();
+        self.loop_depth -= 1;
+        body
+    }
+
+    /// statements separated by `;`, up to one of `terminators` or EOF.
+    /// Syntax errors are recorded and the broken statements skipped
+    fn statement_list(&mut self, terminators: &[Token]) -> Vec<Statement> {
+        let mut statements = vec![];
+        loop {
+            let tok = self.get_current();
+            if tok == Token::EOF || Parser::match_types_vec(&tok, terminators) {
+                return statements;
+            }
+            // empty statement
+            if tok == Token::SemiColon {
+                self.move_on(1);
+                continue;
+            }
+
+            let start = self.current;
+            match self.statement() {
+                Ok(statement) => {
+                    statements.push(statement);
+                    let tok = self.get_current();
+                    if tok == Token::SemiColon {
+                        self.move_on(1);
+                    } else if tok != Token::EOF && !Parser::match_types_vec(&tok, terminators) {
+                        self.errors.push(
+                            self.error(ParseErrorKind::MissingSemiColon, vec![Token::SemiColon]),
+                        );
+                    }
+                }
+                Err(e) => {
+                    // the bad token may itself be a synchronization point
+                    if self.current == start {
+                        self.move_on(1);
+                    }
+                    self.report(e);
+                }
+            }
+        }
+    }
+
+    pub fn statement(&mut self) -> Result<Statement, ParseError> {
+        self.nested(Parser::statement_nested)
+    }
+
+    fn statement_nested(&mut self) -> Result<Statement, ParseError> {
+        let start = self.current_span();
+        let checks = self.checks[self.current];
+        // every kind of statement is parsed in its own function, which keeps
+        // this frame small as statements nest through it
+        let statement_type = match self.get_current() {
+            Token::Identifier(name) if name.eq_ignore_ascii_case("writeln") => self.write(true),
+            Token::Identifier(_) => self.assign_or_call(),
+            Token::Begin => self.compound(),
+            Token::If => self.if_statement(),
+            Token::While => self.while_statement(),
+            Token::Repeat => self.repeat(),
+            Token::For => self.for_statement(),
+            Token::With => self.with(),
+            Token::Break | Token::Continue | Token::Exit => self.jump(),
+            Token::Var => {
+                self.move_on(1);
+                self.var_names_and_type().map(StatementType::Var)
+            }
+            Token::Read | Token::Sort | Token::Sqrt | Token::Abs | Token::Len => {
+                self.builtin_call()
+            }
+            Token::Write => self.write(false),
+            _ => Err(self.error(ParseErrorKind::ExpectedStatement, vec![])),
+        }?;
+        Ok(Statement::new(
+            statement_type,
+            self.span_from(start),
+            checks,
+        ))
+    }
+
+    /// a statement starting with a name, an assignment or a procedure call
+    fn assign_or_call(&mut self) -> Result<StatementType, ParseError> {
+        let name = self.identifier()?;
+        let target = self.selectors(Box::new(Expr::Literals(name.clone())))?;
+        Ok(match self.get_current() {
+            Token::Assign => {
+                self.move_on(1);
+                StatementType::Assign((*target, self.expression()?))
+            }
+            // an element or field can only be assigned
+            _ if !matches!(*target, Expr::Literals(_)) => {
+                return Err(self.error(ParseErrorKind::UnexpectedToken, vec![Token::Assign]))
+            }
+            // procedure call, the parentheses are optional without arguments
+            Token::OParen => StatementType::ProcCall((name, self.arguments()?.0)),
+            _ => StatementType::ProcCall((name, vec![])),
+        })
+    }
+
+    fn compound(&mut self) -> Result<StatementType, ParseError> {
+        self.move_on(1);
+        let statements = self.statement_list(&[Token::End]);
+        self.consume(Token::End, ParseErrorKind::UnexpectedToken)?;
+        Ok(StatementType::Compound(statements))
     }
-    fn primary(&mut self) -> Box<Expr> {
-        let x = self.get_current();
+
+    fn if_statement(&mut self) -> Result<StatementType, ParseError> {
         self.move_on(1);
-        match x {
-            Token::BooleanLiteral(_b) => Box::new(Expr::Literals(x)),
-            Token::StringLiteral(_b) => Box::new(Expr::Literals(Token::StringLiteral(_b))),
-            Token::IntegerLiteral(_b) => Box::new(Expr::Literals(x)),
-            Token::FloatLiteral(_b) => Box::new(Expr::Literals(x)),
-            Token::Identifier(_b) => Box::new(Expr::Literals(Token::Identifier(_b))),
-            Token::OParen => {
-                self.src
-                    .iter()
-                    .enumerate()
-                    .position(|(index, x)| *x == Token::CParen && index >= self.current)
-                    .expect("Closed parenthesis expected");
-                let expr = self.expression();
-                self.move_on(1); //move out of Closed parenthesis
-                return Box::new(Expr::Grouping(Box::new(expr)));
+        let condition = self.expression()?;
+        self.consume(Token::Then, ParseErrorKind::UnexpectedToken)?;
+        let then_branch = Box::new(self.statement()?);
+        let mut else_branch = None;
+        if self.get_current() == Token::Else {
+            self.move_on(1);
+            else_branch = Some(Box::new(self.statement()?));
+        }
+        Ok(StatementType::If((condition, then_branch, else_branch)))
+    }
+
+    fn while_statement(&mut self) -> Result<StatementType, ParseError> {
+        self.move_on(1);
+        let condition = self.expression()?;
+        self.consume(Token::Do, ParseErrorKind::UnexpectedToken)?;
+        Ok(StatementType::While((
+            condition,
+            Box::new(self.loop_body()?),
+        )))
+    }
+
+    fn repeat(&mut self) -> Result<StatementType, ParseError> {
+        self.move_on(1);
+        self.loop_depth += 1;
+        let body = self.statement_list(&[Token::Until]);
+        self.loop_depth -= 1;
+        self.consume(Token::Until, ParseErrorKind::UnexpectedToken)?;
+        Ok(StatementType::Repeat((body, self.expression()?)))
+    }
+
+    fn for_statement(&mut self) -> Result<StatementType, ParseError> {
+        self.move_on(1);
+        let name = self.identifier()?;
+        self.consume(Token::Assign, ParseErrorKind::UnexpectedToken)?;
+        let from = self.expression()?;
+        let downto = match self.get_current() {
+            Token::To => false,
+            Token::Downto => true,
+            _ => {
+                return Err(self.error(
+                    ParseErrorKind::UnexpectedToken,
+                    vec![Token::To, Token::Downto],
+                ))
             }
-            _ => panic!("Expected expression"),
+        };
+        self.move_on(1);
+        let to = self.expression()?;
+        self.consume(Token::Do, ParseErrorKind::UnexpectedToken)?;
+        Ok(StatementType::For((
+            name,
+            from,
+            downto,
+            to,
+            Box::new(self.loop_body()?),
+        )))
+    }
+
+    fn with(&mut self) -> Result<StatementType, ParseError> {
+        self.move_on(1);
+        let mut records = vec![self.expression()?];
+        while self.get_current() == Token::Comma {
+            self.move_on(1);
+            records.push(self.expression()?);
         }
+        self.consume(Token::Do, ParseErrorKind::UnexpectedToken)?;
+        Ok(StatementType::With((records, Box::new(self.statement()?))))
     }
 
-    fn unary(&mut self) -> Box<Expr> {
-        let term_tokens = vec![Token::Not, Token::Minus];
+    /// `exit`, or `break` and `continue` which need an enclosing loop
+    fn jump(&mut self) -> Result<StatementType, ParseError> {
+        let tok = self.get_current();
+        if tok != Token::Exit && self.loop_depth == 0 {
+            return Err(self.error(ParseErrorKind::OutsideLoop, vec![]));
+        }
+        self.move_on(1);
+        Ok(match tok {
+            Token::Break => StatementType::Break,
+            Token::Continue => StatementType::Continue,
+            _ => StatementType::Exit,
+        })
+    }
 
-        //we consume consecutive unary
+    /// a builtin procedure, or a builtin function called for nothing
+    fn builtin_call(&mut self) -> Result<StatementType, ParseError> {
+        let name = self.get_current_spanned();
+        self.move_on(1);
+        let args = match self.get_current() {
+            Token::OParen => self.arguments()?.0,
+            _ => vec![],
+        };
+        Ok(StatementType::ProcCall((name, args)))
+    }
 
-        let x = self.get_current();
-        while x != Token::EOF {
-            if Parser::match_types_vec(&x, &term_tokens) {
+    /// `write` or `writeln` and its arguments, the parentheses are optional without any
+    fn write(&mut self, newline: bool) -> Result<StatementType, ParseError> {
+        self.move_on(1);
+        let mut args = vec![];
+        if self.get_current() != Token::OParen {
+            return Ok(StatementType::Write((args, newline)));
+        }
+        self.move_on(1);
+        if self.get_current() != Token::CParen {
+            args.push(self.write_arg()?);
+            while self.get_current() == Token::Comma {
                 self.move_on(1);
-                let expr = self.unary();
-                return Box::new(Expr::Unary((x, expr)));
-            } else {
+                args.push(self.write_arg()?);
+            }
+        }
+        self.consume(Token::CParen, ParseErrorKind::MissingCloseParen)?;
+        Ok(StatementType::Write((args, newline)))
+    }
+
+    fn write_arg(&mut self) -> Result<WriteArg, ParseError> {
+        let value = self.expression()?;
+        let mut format = [None, None];
+        for part in &mut format {
+            if self.get_current() != Token::Colon {
                 break;
             }
+            self.move_on(1);
+            *part = Some(self.expression()?);
+        }
+        let [width, precision] = format;
+        Ok(WriteArg {
+            value,
+            width,
+            precision,
+        })
+    }
+
+    // Recursive Descent Grammar
+    pub fn expression(&mut self) -> Result<Expr, ParseError> {
+        Ok(*self.nested(Parser::or)?)
+    }
+    /// parenthesized call arguments and the span of the closing parenthesis
+    fn arguments(&mut self) -> Result<(Vec<Expr>, Span), ParseError> {
+        self.consume(Token::OParen, ParseErrorKind::UnexpectedToken)?;
+        let mut args = vec![];
+        if self.get_current() != Token::CParen {
+            args.push(self.expression()?);
+            while self.get_current() == Token::Comma {
+                self.move_on(1);
+                args.push(self.expression()?);
+            }
+        }
+        let close = self.consume(Token::CParen, ParseErrorKind::MissingCloseParen)?;
+        Ok((args, close.span))
+    }
+
+    fn primary(&mut self) -> Result<Box<Expr>, ParseError> {
+        let x = self.get_current_spanned();
+        let starts_expression = matches!(
+            x.token,
+            Token::BooleanLiteral(_)
+                | Token::StringLiteral(_)
+                | Token::IntegerLiteral(_)
+                | Token::FloatLiteral(_)
+                | Token::Endl
+                | Token::Identifier(_)
+                | Token::Sqrt
+                | Token::Abs
+                | Token::Len
+                | Token::OParen
+        );
+        // a bad token is left for recovery, it may be the `;` ending the statement
+        if !starts_expression {
+            return Err(self.error(ParseErrorKind::ExpectedExpression, vec![]));
+        }
+        self.move_on(1);
+        match x.token {
+            Token::BooleanLiteral(_)
+            | Token::StringLiteral(_)
+            | Token::IntegerLiteral(_)
+            | Token::FloatLiteral(_)
+            | Token::Endl => Ok(Box::new(Expr::Literals(x))),
+            Token::Identifier(_) if self.get_current() == Token::OParen => {
+                let (args, close) = self.arguments()?;
+                let span = x.span.to(close);
+                Ok(Box::new(Expr::Call((x, args, span))))
+            }
+            Token::Identifier(_) => Ok(Box::new(Expr::Literals(x))),
+            // builtin functions are keywords, always called with arguments
+            Token::Sqrt | Token::Abs | Token::Len => {
+                let (args, close) = self.arguments()?;
+                let span = x.span.to(close);
+                Ok(Box::new(Expr::Call((x, args, span))))
+            }
+            _ => {
+                let expr = self.expression()?;
+                let close = self.consume(Token::CParen, ParseErrorKind::MissingCloseParen)?;
+                Ok(Box::new(Expr::Grouping((
+                    Box::new(expr),
+                    x.span.to(close.span),
+                ))))
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Box<Expr>, ParseError> {
+        let term_tokens = vec![Token::Not, Token::Minus];
+
+        //we consume consecutive unary
+
+        let x = self.get_current_spanned();
+        if Parser::match_types_vec(&x.token, &term_tokens) {
+            self.move_on(1);
+            let expr = self.nested(Parser::unary)?;
+            return Ok(Box::new(Expr::Unary((x, expr))));
         }
         //if we break the loop, means we get to highest precedence
         // which is primary
-        self.primary()
+        let expr = self.primary()?;
+        self.selectors(expr)
     }
 
-    fn power(&mut self) -> Box<Expr> {
+    /// any `[i, j]` or `.field` following an expression, `a[i, j]` is `a[i][j]`
+    fn selectors(&mut self, mut expr: Box<Expr>) -> Result<Box<Expr>, ParseError> {
+        loop {
+            match self.get_current() {
+                Token::OBracket => self.move_on(1),
+                Token::Dot => {
+                    self.move_on(1);
+                    let field = self.identifier()?;
+                    expr = Box::new(Expr::Field((expr, field)));
+                    continue;
+                }
+                _ => return Ok(expr),
+            }
+            let mut indexes = vec![self.expression()?];
+            while self.get_current() == Token::Comma {
+                self.move_on(1);
+                indexes.push(self.expression()?);
+            }
+            let close = self.consume(Token::CBracket, ParseErrorKind::UnexpectedToken)?;
+            for index in indexes {
+                let span = expr.span().to(close.span);
+                expr = Box::new(Expr::Index((expr, Box::new(index), span)));
+            }
+        }
+    }
+
+    fn power(&mut self) -> Result<Box<Expr>, ParseError> {
         //same logic as fn equality
-        let mut expr = self.unary();
+        let mut expr = self.unary()?;
         let term_tokens = vec![Token::Pow];
 
         while self.get_current() != Token::EOF {
-            let x = self.get_current();
-            if Parser::match_types_vec(&x, &term_tokens) {
+            let x = self.get_current_spanned();
+            if Parser::match_types_vec(&x.token, &term_tokens) {
                 self.move_on(1);
-                let rhs = self.unary();
+                let rhs = self.unary()?;
                 expr = Box::new(Expr::Binary((expr, x, rhs)));
             } else {
                 break;
             }
         }
-        expr
+        Ok(expr)
     }
 
-    fn factor(&mut self) -> Box<Expr> {
+    fn factor(&mut self) -> Result<Box<Expr>, ParseError> {
         //same logic as fn equality
-        let mut expr = self.power();
-        let term_tokens = vec![Token::Mul, Token::Div, Token::Mod];
+        let mut expr = self.power()?;
+        let term_tokens = vec![Token::Mul, Token::Slash, Token::Div, Token::Mod];
 
         while self.get_current() != Token::EOF {
-            let x = self.get_current();
-            if Parser::match_types_vec(&x, &term_tokens) {
+            let x = self.get_current_spanned();
+            if Parser::match_types_vec(&x.token, &term_tokens) {
                 self.move_on(1);
-                let rhs = self.power();
+                let rhs = self.power()?;
                 expr = Box::new(Expr::Binary((expr, x, rhs)));
             } else {
                 break;
             }
         }
-        expr
+        Ok(expr)
     }
-    fn term(&mut self) -> Box<Expr> {
+    fn term(&mut self) -> Result<Box<Expr>, ParseError> {
         //same logic as fn equality
-        let mut expr = self.factor();
+        let mut expr = self.factor()?;
         let term_tokens = vec![Token::Plus, Token::Minus];
 
         while self.get_current() != Token::EOF {
-            let x = self.get_current();
-            if Parser::match_types_vec(&x, &term_tokens) {
+            let x = self.get_current_spanned();
+            if Parser::match_types_vec(&x.token, &term_tokens) {
                 self.move_on(1);
-                let rhs = self.factor();
+                let rhs = self.factor()?;
                 expr = Box::new(Expr::Binary((expr, x, rhs)));
             } else {
                 break;
             }
         }
-        expr
+        Ok(expr)
     }
 
-    fn comparison(&mut self) -> Box<Expr> {
+    fn comparison(&mut self) -> Result<Box<Expr>, ParseError> {
         //same logic as fn equality
-        let mut expr = self.term();
+        let mut expr = self.term()?;
         let comparison_tokens = vec![Token::Great, Token::GreatEq, Token::Less, Token::LessEq];
 
         while self.get_current() != Token::EOF {
-            let x = self.get_current();
-            if Parser::match_types_vec(&x, &comparison_tokens) {
+            let x = self.get_current_spanned();
+            if Parser::match_types_vec(&x.token, &comparison_tokens) {
                 self.move_on(1);
-                let rhs = self.term();
+                let rhs = self.term()?;
                 expr = Box::new(Expr::Binary((expr, x, rhs)));
             } else {
                 break;
             }
         }
-        expr
+        Ok(expr)
     }
-    fn equality(&mut self) -> Box<Expr> {
+    fn equality(&mut self) -> Result<Box<Expr>, ParseError> {
         // for e.g a1 == a2   == a3  == a4
         // ai is an expr of higher order than equality
 
-        let mut expr = self.comparison(); //a1
+        let mut expr = self.comparison()?; //a1
 
         //consuming all ai, til all == are parsed or EOF
         while self.get_current() != Token::EOF {
-            let x = self.get_current();
-            if Parser::match_types_vec(&x, &vec![Token::Eq, Token::Neq]) {
+            let x = self.get_current_spanned();
+            if Parser::match_types_vec(&x.token, &[Token::Eq, Token::Neq]) {
                 self.move_on(1);
                 //comparison is next higher order
-                let rhs = self.comparison();
+                let rhs = self.comparison()?;
 
                 //consume into original expr
                 expr = Box::new(Expr::Binary((expr, x, rhs)));
@@ -233,6 +901,32 @@ impl Parser {
                 break;
             }
         }
-        expr
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Box<Expr>, ParseError> {
+        //same logic as fn equality
+        let mut expr = self.equality()?;
+
+        while self.get_current() == Token::And {
+            let x = self.get_current_spanned();
+            self.move_on(1);
+            let rhs = self.equality()?;
+            expr = Box::new(Expr::Binary((expr, x, rhs)));
+        }
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<Box<Expr>, ParseError> {
+        //same logic as fn equality
+        let mut expr = self.and()?;
+
+        while self.get_current() == Token::Or {
+            let x = self.get_current_spanned();
+            self.move_on(1);
+            let rhs = self.and()?;
+            expr = Box::new(Expr::Binary((expr, x, rhs)));
+        }
+        Ok(expr)
     }
 }
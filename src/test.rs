@@ -1,122 +1,1468 @@
-#[cfg(test)]
-mod test {
-
-    use crate::{
-        tokenizer::{self, Tokenizer},
-        types::Token,
-    };
-
-    #[test]
-    pub fn single_token() {
-        let test_inp = vec!["+", "-", "*", ";", "^", "/", "="];
-        for operator in test_inp {
-            let operator = operator.to_string();
-            let mut tokenizer = Tokenizer::new(&operator);
-            let result = tokenizer
-                .lex_next_token()
-                .expect("Cannot parse single token");
-            // assert!(matches!(result, Token));
-        }
-    }
-    #[test]
-    pub fn double_tokens() {
-        let test_inp = vec!["<", ">", ">=", "<=", ":="];
-
-        for operator in test_inp {
-            let operator = operator.to_string();
-            let mut tokenizer = Tokenizer::new(&operator);
-
-            let result = tokenizer
-                .lex_next_token()
-                .expect("Cannot parse single token");
-        }
-    }
-
-    #[test]
-    pub fn literals() {
-        let test_inp = vec!["'this is a string'", "124124", "12.4124"];
-
-        let mut tokenizer = Tokenizer::new(test_inp[0]);
-        if let Token::StringLiteral(x) = tokenizer.lex_next_token().unwrap() {
-            assert_eq!(x, "this is a string");
-            // println!("{}", x);
-        } else {
-            panic!("Cannot tokenize string literal")
-        };
-
-        let mut tokenizer = Tokenizer::new(test_inp[1]);
-        if let Token::IntegerLiteral(x) = tokenizer.lex_next_token().unwrap() {
-            assert_eq!(x, 124124);
-            // println!("{}", x);
-        } else {
-            panic!("Cannot tokenize integer literal")
-        };
-
-        let mut tokenizer = Tokenizer::new(test_inp[2]);
-        if let Token::FloatLiteral(x) = tokenizer.lex_next_token().unwrap() {
-            assert_eq!(x, 12.4124);
-            // println!("{}", x);
-        } else {
-            panic!("Cannot tokenize float literal")
-        };
-    }
-    #[test]
-    pub fn identifier_or_keywords() {
-        let test_identifier = "thisisaname";
-        let mut tokenizer = Tokenizer::new(test_identifier);
-
-        if let Token::Identifier(x) = tokenizer
-            .lex_next_token()
-            .expect("Cannot parse single token")
-        {
-            assert_eq!(x, "thisisaname");
-            // println!("{}", x);
-        } else {
-            panic!("Cannot lex identifiers");
-        }
-
-        let test_keywords = vec!["var", "if", "then"];
-        let mut result_keywords: Vec<String> = vec![];
-        for keyword in &test_keywords {
-            let keyword = keyword.to_string();
-            let tok = Tokenizer::new(&keyword)
-                .lex_next_token()
-                .unwrap()
-                .to_string();
-            result_keywords.push(tok);
-        }
-        assert_eq!(result_keywords, test_keywords);
-
-        let test_keyword_inside_identifier = "varasdf";
-        let tok = Tokenizer::new(&test_keyword_inside_identifier)
-            .lex_next_token()
-            .unwrap();
-        if let Token::Identifier(x) = tok {
-            assert_eq!(x, test_keyword_inside_identifier);
-        } else {
-            panic!("Lex identifier");
-        }
-    }
-
-    #[test]
-    pub fn comments() {
-        let test_inp = "{this is a comment} var a:=1;";
-        let result_toks = vec![
-            Token::Comment,
-            Token::Var,
-            Token::Identifier("a".to_string()),
-            Token::Assign,
-            Token::IntegerLiteral(1),
-        ];
-
-        let mut tokenizer = Tokenizer::new(&test_inp);
-        let toks = tokenizer
-            .tokenize_full_src()
-            .expect("Cannot parse single token");
-
-        for i in 0..result_toks.len() {
-            // println!("{}", toks[i]);
-            assert_eq!(toks[i], result_toks[i]);
-        }
-    }
-}
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod test {
+
+    use std::cmp::Ordering;
+
+    use crate::{
+        error::{
+            LexErrorKind, ParseError, ParseErrorKind, RuntimeError, RuntimeErrorKind, TypeError,
+            TypeErrorKind,
+        },
+        interpreter::{Interpreter, DEFAULT_MAX_DEPTH},
+        parser::Parser,
+        tokenizer::Tokenizer,
+        typeck::TypeChecker,
+        types::{Declaration, Expr, Program, Span, SpannedToken, Token, Value},
+    };
+
+    #[test]
+    pub fn single_token() {
+        let test_inp = ["+", "-", "*", ";", "^", "/", "="];
+        for operator in test_inp {
+            let operator = operator.to_string();
+            let mut tokenizer = Tokenizer::new(&operator);
+            let result = tokenizer
+                .lex_next_token()
+                .expect("Cannot parse single token");
+            assert_eq!(result.span.len, 1);
+        }
+    }
+    #[test]
+    pub fn double_tokens() {
+        let test_inp = ["<", ">", ">=", "<=", ":="];
+
+        for operator in test_inp {
+            let operator = operator.to_string();
+            let mut tokenizer = Tokenizer::new(&operator);
+
+            let result = tokenizer
+                .lex_next_token()
+                .expect("Cannot parse single token");
+            assert_eq!(result.span.len, operator.len());
+        }
+    }
+
+    #[test]
+    pub fn literals() {
+        let test_inp = ["'this is a string'", "124124", "12.4124"];
+
+        let mut tokenizer = Tokenizer::new(test_inp[0]);
+        if let Token::StringLiteral(x) = tokenizer.lex_next_token().unwrap().token {
+            assert_eq!(x, "this is a string");
+            // println!("{}", x);
+        } else {
+            panic!("Cannot tokenize string literal")
+        };
+
+        let mut tokenizer = Tokenizer::new(test_inp[1]);
+        if let Token::IntegerLiteral(x) = tokenizer.lex_next_token().unwrap().token {
+            assert_eq!(x, 124124);
+            // println!("{}", x);
+        } else {
+            panic!("Cannot tokenize integer literal")
+        };
+
+        let mut tokenizer = Tokenizer::new(test_inp[2]);
+        if let Token::FloatLiteral(x) = tokenizer.lex_next_token().unwrap().token {
+            assert_eq!(x, 12.4124);
+            // println!("{}", x);
+        } else {
+            panic!("Cannot tokenize float literal")
+        };
+    }
+    #[test]
+    pub fn identifier_or_keywords() {
+        let test_identifier = "thisisaname";
+        let mut tokenizer = Tokenizer::new(test_identifier);
+
+        if let Token::Identifier(x) = tokenizer
+            .lex_next_token()
+            .expect("Cannot parse single token")
+            .token
+        {
+            assert_eq!(x, "thisisaname");
+            // println!("{}", x);
+        } else {
+            panic!("Cannot lex identifiers");
+        }
+
+        let test_keywords = vec!["var", "if", "then"];
+        let mut result_keywords: Vec<String> = vec![];
+        for keyword in &test_keywords {
+            let keyword = keyword.to_string();
+            let tok = Tokenizer::new(&keyword)
+                .lex_next_token()
+                .unwrap()
+                .token
+                .to_string();
+            result_keywords.push(tok);
+        }
+        assert_eq!(result_keywords, test_keywords);
+
+        let test_keyword_inside_identifier = "varasdf";
+        let tok = Tokenizer::new(test_keyword_inside_identifier)
+            .lex_next_token()
+            .unwrap()
+            .token;
+        if let Token::Identifier(x) = tok {
+            assert_eq!(x, test_keyword_inside_identifier);
+        } else {
+            panic!("Lex identifier");
+        }
+    }
+
+    #[test]
+    pub fn string_literals() {
+        let cases = [
+            (r"'it\'s'", "it's"),
+            ("'it''s'", "it's"),
+            (r"'a\tb\\c\n'", "a\tb\\c\n"),
+            ("'line'#13#10'next'", "line\r\nnext"),
+            ("#65#$42", "AB"),
+            ("''", ""),
+        ];
+        for (src, expected) in cases {
+            let toks = Tokenizer::new(src).tokenize_full_src().unwrap();
+            assert_eq!(toks[0].token, Token::StringLiteral(expected.to_string()));
+            assert_eq!(toks[0].span.len, src.len());
+            assert_eq!(toks[1].token, Token::EOF);
+        }
+
+        let error = |src| match Tokenizer::new(src).tokenize_full_src() {
+            Err(e) => e,
+            other => panic!("expected a lex error, got {:?}", other),
+        };
+        let e = error("a := 'open\nb");
+        assert_eq!(e.kind, LexErrorKind::UnterminatedString);
+        assert_eq!(e.span, Span::new(5, 1, 6, 5));
+        let e = error(r"'bad \q'");
+        assert_eq!(e.to_string(), r"1:6: unknown escape sequence `\q`");
+        assert_eq!(error(r"'end\").kind, LexErrorKind::UnterminatedEscape);
+        assert_eq!(
+            error("'x'#").kind,
+            LexErrorKind::InvalidCharCode("#".to_string())
+        );
+        assert_eq!(
+            error("#1114112").kind,
+            LexErrorKind::InvalidCharCode("#1114112".to_string())
+        );
+    }
+
+    #[test]
+    pub fn number_literals() {
+        let cases = [
+            ("$FF", Token::IntegerLiteral(255)),
+            ("0xff", Token::IntegerLiteral(255)),
+            ("%1010", Token::IntegerLiteral(10)),
+            ("&17", Token::IntegerLiteral(15)),
+            ("1_000_000", Token::IntegerLiteral(1_000_000)),
+            ("1.5e-3", Token::FloatLiteral(1.5e-3)),
+            ("2E10", Token::FloatLiteral(2e10)),
+            ("3.25", Token::FloatLiteral(3.25)),
+        ];
+        for (src, expected) in cases {
+            let toks = Tokenizer::new(src).tokenize_full_src().unwrap();
+            assert_eq!(toks[0].token, expected);
+            assert_eq!(toks[0].span.len, src.len());
+            assert_eq!(toks[1].token, Token::EOF);
+        }
+        // after an operand `&` is still an and
+        let tokens = |src| -> Vec<Token> {
+            let toks = Tokenizer::new(src).tokenize_full_src().unwrap();
+            toks.into_iter().map(|t| t.token).collect()
+        };
+        assert_eq!(tokens("a & b")[1], Token::And);
+        assert_eq!(
+            tokens("x&7 & (&7)"),
+            vec![
+                Token::Identifier("x".to_string()),
+                Token::And,
+                Token::IntegerLiteral(7),
+                Token::And,
+                Token::OParen,
+                Token::IntegerLiteral(7),
+                Token::CParen,
+                Token::EOF,
+            ]
+        );
+        assert_eq!(tokens("$FE_FF")[0], Token::IntegerLiteral(0xFEFF));
+        assert_eq!(tokens("1..2")[1], Token::DotDot);
+
+        let error = |src| match Tokenizer::new(src).tokenize_full_src() {
+            Err(e) => e,
+            other => panic!("expected a lex error, got {:?}", other),
+        };
+        let e = error("x := 99999999999999999999;");
+        assert_eq!(
+            e.kind,
+            LexErrorKind::IntegerOutOfRange("99999999999999999999".to_string())
+        );
+        assert_eq!(e.span, Span::new(5, 1, 6, 20));
+        assert_eq!(
+            error("$FFFFFFFFFFFFFFFFF").to_string(),
+            "1:1: integer literal `$FFFFFFFFFFFFFFFFF` out of range"
+        );
+        for bad in [
+            "%102", "$", "12ab", "1e", "1__0", "1_", "$FG", "12.", "3.e5",
+        ] {
+            assert_eq!(
+                error(bad).kind,
+                LexErrorKind::InvalidNumber(bad.to_string())
+            );
+        }
+        assert_eq!(
+            error("1e999").kind,
+            LexErrorKind::RealOutOfRange("1e999".to_string())
+        );
+    }
+
+    #[test]
+    pub fn comments() {
+        let test_inp = "{this is a comment} var a:=1;";
+        let result_toks = [
+            Token::Var,
+            Token::Identifier("a".to_string()),
+            Token::Assign,
+            Token::IntegerLiteral(1),
+        ];
+
+        let mut tokenizer = Tokenizer::new(test_inp);
+        let toks = tokenizer
+            .tokenize_full_src()
+            .expect("Cannot parse single token");
+
+        for (tok, expected) in toks.iter().zip(result_toks.iter()) {
+            assert_eq!(&tok.token, expected);
+        }
+
+        // only asked for, whitespace and comments come through
+        let toks: Vec<Token> = Tokenizer::new("{c} a\n b")
+            .with_trivia()
+            .map(|t| t.unwrap().token)
+            .collect();
+        let ident = |name: &str| Token::Identifier(name.to_string());
+        assert_eq!(
+            toks,
+            vec![
+                Token::Comment,
+                Token::WhiteSpace,
+                ident("a"),
+                Token::WhiteSpace,
+                ident("b"),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn tokenizer_iterator() {
+        for src in ["", "   \n\t ", "{ only a comment }  "] {
+            assert_eq!(Tokenizer::new(src).count(), 0);
+            let toks = Tokenizer::new(src).tokenize_full_src().unwrap();
+            assert_eq!(toks.len(), 1);
+            assert_eq!(toks[0].token, Token::EOF);
+            assert_eq!(toks[0].span.offset, src.len());
+        }
+
+        let toks: Vec<_> = Tokenizer::new(" a ;\n").collect();
+        assert_eq!(toks.len(), 2);
+        assert_eq!(toks[1].as_ref().unwrap().span, Span::new(3, 1, 4, 1));
+
+        // identifiers are read by char, not by byte
+        let toks = Tokenizer::new("var café: integer; xé")
+            .tokenize_full_src()
+            .unwrap();
+        assert_eq!(toks[1].token, Token::Identifier("café".to_string()));
+        assert_eq!(toks[2].token, Token::Colon);
+        assert_eq!(toks[5].token, Token::Identifier("xé".to_string()));
+        assert_eq!(toks[5].span, Span::new(20, 1, 20, 3));
+        assert_eq!(toks[6].token, Token::EOF);
+
+        // the error ends the stream
+        let mut tokenizer = Tokenizer::new("a ? b");
+        assert!(tokenizer.next().unwrap().is_ok());
+        let e = tokenizer.next().unwrap().unwrap_err();
+        assert_eq!(e.kind, LexErrorKind::InvalidChar('?'));
+        assert_eq!(e.to_string(), "1:3: unexpected character `?`");
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    pub fn comment_styles() {
+        let src = "a // line comment  \n(* block\n { nested } *) { outer { inner } still outer } b / c (d)";
+        let toks: Vec<Token> = Tokenizer::new(src)
+            .tokenize_full_src()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .filter(|t| *t != Token::Comment)
+            .collect();
+        let ident = |name: &str| Token::Identifier(name.to_string());
+        assert_eq!(
+            toks,
+            vec![
+                ident("a"),
+                ident("b"),
+                Token::Slash,
+                ident("c"),
+                Token::OParen,
+                ident("d"),
+                Token::CParen,
+                Token::EOF,
+            ]
+        );
+
+        for (src, column) in [("a { open { closed }", 3), ("begin\n  (* (* x *)", 3)] {
+            match Tokenizer::new(src).tokenize_full_src() {
+                Err(e) => {
+                    assert_eq!(e.kind, LexErrorKind::UnterminatedComment);
+                    assert_eq!(e.span.column, column);
+                }
+                other => panic!("expected a lex error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    pub fn token_spans() {
+        let test_inp = "var a := 'é';\n  a:=a+12.5;";
+        let toks = Tokenizer::new(test_inp).tokenize_full_src().unwrap();
+
+        // 'é' is 4 bytes long: two quotes and a 2 byte char
+        assert_eq!(toks[3].span, Span::new(9, 1, 10, 4));
+        assert_eq!(toks[5].span, Span::new(17, 2, 3, 1));
+        assert_eq!(toks[6].token, Token::Assign);
+        assert_eq!(toks[6].span, Span::new(18, 2, 4, 2));
+        assert_eq!(toks[9].span, Span::new(22, 2, 8, 4));
+        assert_eq!(toks.last().unwrap().token, Token::EOF);
+        assert_eq!(toks.last().unwrap().span.offset, test_inp.len());
+    }
+
+    #[test]
+    pub fn unsupported_expressions() {
+        // trees the parser never builds are errors, not crashes
+        let tok = |token| SpannedToken::new(token, Span::new(0, 1, 1, 1));
+        let one = || Box::new(Expr::Literals(tok(Token::IntegerLiteral(1))));
+        let mut out = vec![];
+        let mut interpreter = Interpreter::new(&mut out);
+        for expr in [
+            Expr::Binary((one(), tok(Token::DotDot), one())),
+            Expr::Unary((tok(Token::Mul), one())),
+            Expr::Literals(tok(Token::SemiColon)),
+        ] {
+            let e = interpreter.evaluate(&expr).unwrap_err();
+            assert!(
+                matches!(e.kind, RuntimeErrorKind::Unsupported(_)),
+                "{:?}",
+                e
+            );
+        }
+    }
+
+    #[test]
+    pub fn expression_spans() {
+        let test_inp = "1 + (2 * 3)";
+        let toks = Tokenizer::new(test_inp).tokenize_full_src().unwrap();
+        let expr = Parser::new(toks).expression().unwrap();
+
+        assert_eq!(expr.span(), Span::new(0, 1, 1, test_inp.len()));
+        let mut out = vec![];
+        assert_eq!(
+            Interpreter::new(&mut out).evaluate(&expr),
+            Ok(Value::Integer(7))
+        );
+    }
+
+    fn parse_statement(src: &str) -> Result<crate::types::Statement, ParseError> {
+        let toks = Tokenizer::new(src).tokenize_full_src().unwrap();
+        Parser::new(toks).statement()
+    }
+
+    fn parse_program(src: &str) -> (Program, Vec<ParseError>) {
+        let toks = Tokenizer::new(src).tokenize_full_src().unwrap();
+        Parser::new(toks).program()
+    }
+
+    #[test]
+    pub fn parse_errors() {
+        let err = parse_statement("write('hi';").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::MissingCloseParen);
+        assert_eq!(err.expected, vec![Token::CParen]);
+        assert_eq!(err.found, Token::SemiColon);
+        assert_eq!(err.span, Span::new(10, 1, 11, 1));
+
+        let (_, errors) = parse_program("begin write('hi') write('x') end.");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ParseErrorKind::MissingSemiColon);
+        assert_eq!(errors[0].found, Token::Write);
+
+        let err = parse_statement("write('a' + );").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::ExpectedExpression);
+        assert_eq!(err.found, Token::CParen);
+        assert_eq!(err.to_string(), "1:13: expected expression, found `)`");
+
+        assert!(parse_statement("write('hi')").is_ok());
+    }
+
+    #[test]
+    pub fn parse_error_recovery() {
+        let src = "begin write('a';\nwrite(1:);\nwrite('ok');\nwrite('b' 'c');\nwrite('done') end.";
+        let (program, errors) = parse_program(src);
+
+        assert_eq!(program.block.body.len(), 2);
+        let kinds: Vec<_> = errors
+            .iter()
+            .map(|e| (e.kind.clone(), e.span.line))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (ParseErrorKind::MissingCloseParen, 1),
+                (ParseErrorKind::ExpectedExpression, 2),
+                (ParseErrorKind::MissingCloseParen, 4),
+            ]
+        );
+
+        // the `;` a bad expression stops at still ends its statement
+        let src = "var x: integer;\nbegin\n  x := ;\n  x := ;\n  x := ;\n  x := 1\nend.";
+        let (program, errors) = parse_program(src);
+        assert_eq!(program.block.body.len(), 1);
+        let lines: Vec<_> = errors
+            .iter()
+            .map(|e| (e.kind.clone(), e.span.line))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (ParseErrorKind::ExpectedExpression, 3),
+                (ParseErrorKind::ExpectedExpression, 4),
+                (ParseErrorKind::ExpectedExpression, 5),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn parse_program_sections() {
+        let src = "program Demo;
+{ header comment }
+const N = 10; Name = 'x';
+type Count = integer;
+var a, b: integer;
+    r: real;
+function add(x, y: integer; z: real): real;
+var t: integer;
+begin
+  write('in add')
+end;
+procedure hello;
+begin
+end;
+begin
+  write('main');
+end.
+";
+        let (program, errors) = parse_program(src);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let decls = &program.block.declarations;
+        assert_eq!(decls.len(), 7);
+        assert!(matches!(decls[2], Declaration::Type(_)));
+        match &decls[3] {
+            Declaration::Var(var) => assert_eq!(var.names.len(), 2),
+            other => panic!("expected var, got {:?}", other),
+        }
+        match &decls[5] {
+            Declaration::Routine(f) => {
+                assert_eq!(f.params.len(), 2);
+                assert!(f.return_type.is_some());
+                assert_eq!(f.block.declarations.len(), 1);
+                assert_eq!(f.block.body.len(), 1);
+            }
+            other => panic!("expected function, got {:?}", other),
+        }
+        match &decls[6] {
+            Declaration::Routine(p) => {
+                assert!(p.params.is_empty() && p.return_type.is_none());
+                assert!(p.block.body.is_empty());
+            }
+            other => panic!("expected procedure, got {:?}", other),
+        }
+        assert_eq!(program.block.body.len(), 1);
+    }
+
+    #[test]
+    pub fn parse_program_errors_keep_partial_ast() {
+        let src = "var a integer; b: real;\nbegin write('x') end";
+        let (program, errors) = parse_program(src);
+
+        // `a` is dropped, `b` and the body survive
+        assert_eq!(program.block.declarations.len(), 1);
+        assert_eq!(program.block.body.len(), 1);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].expected, vec![Token::Colon, Token::Assign]);
+        assert_eq!(errors[1].expected, vec![Token::Dot]);
+        assert_eq!(errors[1].found, Token::EOF);
+    }
+
+    /// parse and run `src`, returning what it wrote and the final value of `var`
+    fn run_program(src: &str, var: &str) -> (String, Option<Value>) {
+        let (program, errors) = parse_program(src);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let mut out = vec![];
+        let mut interpreter = Interpreter::new(&mut out);
+        interpreter.run(&program).unwrap();
+        let value = interpreter.env().get(var).ok();
+        (String::from_utf8(out).unwrap(), value)
+    }
+
+    #[test]
+    pub fn interpret_statements() {
+        let src = "const Limit = 5;
+var i, total, n: integer;
+begin
+  total := 0;
+  for i := 1 to Limit do
+    total := total + i;
+  n := 0;
+  while n < 3 do
+  begin
+    n := n + 1;
+    write('.')
+  end;
+  if total = 15 then write('yes') else write('no');
+  if n > 5 then write('never')
+end.";
+        let (out, total) = run_program(src, "TOTAL");
+        assert_eq!(out, "...yes");
+        assert_eq!(total, Some(Value::Integer(15)));
+    }
+
+    #[test]
+    pub fn type_declarations_are_scoped() {
+        let src = "type T = integer;
+procedure p;
+type T = string;
+var s: T;
+begin s := 'local' end;
+var y: T;
+begin
+  p;
+  y := 1
+end.";
+        assert!(type_errors(src).is_empty());
+        assert_eq!(run_program(src, "y").1, Some(Value::Integer(1)));
+    }
+
+    #[test]
+    pub fn variables_must_be_assigned_before_use() {
+        let src = "type Count = integer; var c: Count; flag: boolean; begin c := 1 end.";
+        assert_eq!(run_program(src, "c").1, Some(Value::Integer(1)));
+        assert_eq!(run_program(src, "flag").1, None);
+
+        let err = run_error("var a, b: integer; begin a := b + 1 end.");
+        assert_eq!(
+            err.kind,
+            RuntimeErrorKind::UninitializedVariable("b".to_string())
+        );
+        assert_eq!(err.span, Span::new(30, 1, 31, 1));
+
+        let err = run_error("var a: integer; begin a := 'text' end.");
+        assert_eq!(
+            err.kind,
+            RuntimeErrorKind::TypeMismatch("cannot assign string to integer".to_string())
+        );
+        let err = run_error("const Max = 3; begin Max := 4 end.");
+        assert_eq!(
+            err.kind,
+            RuntimeErrorKind::AssignToConstant("Max".to_string())
+        );
+    }
+
+    #[test]
+    pub fn control_flow() {
+        let src = "var i, n, total: integer;
+begin
+  total := 0;
+  for i := 3 downto 1 do
+    total := total * 10 + i;
+  n := 0;
+  repeat
+    n := n + 1;
+    if n mod 2 = 0 then continue;
+    if n > 6 then break;
+    write('.')
+  until n = 10;
+  for i := 5 to 1 do write('never');
+  while true do break
+end.";
+        let (out, total) = run_program(src, "total");
+        assert_eq!(out, "...");
+        assert_eq!(total, Some(Value::Integer(321)));
+        assert_eq!(run_program(src, "n").1, Some(Value::Integer(7)));
+
+        let err = run_error("var i: integer; begin for i := 1 to 3 do i := 5 end.");
+        assert_eq!(
+            err.kind,
+            RuntimeErrorKind::LoopVariableAssigned("i".to_string())
+        );
+        assert_eq!(err.span, Span::new(41, 1, 42, 1));
+
+        let (_, errors) = parse_program("begin if true then break end.");
+        assert_eq!(errors[0].kind, ParseErrorKind::OutsideLoop);
+        let (_, errors) = parse_program("var i: integer; begin for i := 1 upto 3 do end.");
+        assert_eq!(errors[0].expected, vec![Token::To, Token::Downto]);
+    }
+
+    #[test]
+    pub fn routines() {
+        let src = "var total, depth: integer;
+function fact(n: integer): integer;
+begin
+  if n <= 1 then fact := 1 else fact := n * fact(n - 1)
+end;
+procedure add(amount: integer; times: integer);
+var i: integer;
+begin
+  for i := 1 to times do
+  begin
+    if total >= 100 then exit;
+    total := total + amount
+  end
+end;
+function outer: integer;
+var hidden: integer;
+  function inner(): integer;
+  begin
+    inner := hidden * 2
+  end;
+begin
+  hidden := 21;
+  outer := inner()
+end;
+begin
+  total := fact(3);
+  add(3, 2);
+  add(50, 3);
+  depth := outer;
+  greet
+end.";
+        let (program, errors) = parse_program(&src.replace("greet", "write('hi')"));
+        assert!(errors.is_empty(), "{:?}", errors);
+        let mut out = vec![];
+        let mut interpreter = Interpreter::new(&mut out);
+        interpreter.run(&program).unwrap();
+        assert_eq!(
+            interpreter.env().get("total").ok(),
+            Some(Value::Integer(112))
+        );
+        assert_eq!(
+            interpreter.env().get("depth").ok(),
+            Some(Value::Integer(42))
+        );
+
+        let err = run_error(src);
+        assert_eq!(
+            err.kind,
+            RuntimeErrorKind::UnknownProcedure("greet".to_string())
+        );
+        let err = run_error(&src.replace("fact(3)", "fact(3, 6)"));
+        assert_eq!(
+            err.kind,
+            RuntimeErrorKind::ArgumentCount("fact".to_string(), 1, 2)
+        );
+        let err = run_error("function f: integer; begin end; begin write('x'); f end.");
+        assert_eq!(err.kind, RuntimeErrorKind::NoResult("f".to_string()));
+    }
+
+    #[test]
+    pub fn parameter_modes() {
+        let src = "var x, y, q, r: integer;
+procedure swap(var a, b: integer);
+var t: integer;
+begin
+  t := a; a := b; b := t
+end;
+procedure divmod(const n, d: integer; var quot, rem: integer);
+  procedure store(var target: integer; value: integer);
+  begin
+    target := value
+  end;
+begin
+  store(quot, n div d);
+  store(rem, n mod d)
+end;
+begin
+  x := 1; y := 2;
+  swap(x, y);
+  divmod(17, 5, q, r)
+end.";
+        assert_eq!(run_program(src, "x").1, Some(Value::Integer(2)));
+        assert_eq!(run_program(src, "y").1, Some(Value::Integer(1)));
+        assert_eq!(run_program(src, "q").1, Some(Value::Integer(3)));
+        assert_eq!(run_program(src, "r").1, Some(Value::Integer(2)));
+
+        let err = run_error(&src.replace("swap(x, y)", "swap(x, y + 1)"));
+        assert_eq!(
+            err.kind,
+            RuntimeErrorKind::NotAssignable("(y + 1)".to_string())
+        );
+        let err = run_error(&src.replace("var x, y,", "var y: integer; x: real; var"));
+        assert_eq!(
+            err.kind,
+            RuntimeErrorKind::TypeMismatch(
+                "var parameter needs a integer variable, found real".to_string()
+            )
+        );
+        let err = run_error(&src.replace("store(quot, n div d)", "n := 0"));
+        assert_eq!(
+            err.kind,
+            RuntimeErrorKind::AssignToConstant("n".to_string())
+        );
+    }
+
+    #[test]
+    pub fn call_depth_limit() {
+        let src = "function down(n: integer): integer;
+begin
+  if n = 0 then down := 0 else down := down(n - 1)
+end;
+var r: integer;
+begin r := down(40) end.";
+        let (program, _) = parse_program(src);
+        let mut out = vec![];
+        let mut interpreter = Interpreter::new(&mut out).with_max_depth(41);
+        assert!(interpreter.run(&program).is_ok());
+
+        let mut interpreter = Interpreter::new(&mut out).with_max_depth(40);
+        let err = interpreter.run(&program).unwrap_err();
+        assert_eq!(err.kind, RuntimeErrorKind::StackOverflow(40));
+        assert_eq!(
+            err.to_string(),
+            "3:40: runtime error: stack overflow: more than 40 nested calls"
+        );
+
+        // nesting within calls counts too, it is what uses the native stack
+        let nested = format!("{}n{}", "(1 + ".repeat(40), ")".repeat(40));
+        let src = format!("var n: integer; begin n := 0; n := {} end.", nested);
+        let (program, _) = parse_program(&src);
+        let mut interpreter = Interpreter::new(&mut out).with_max_depth(2);
+        let err = interpreter.run(&program).unwrap_err();
+        assert_eq!(err.kind, RuntimeErrorKind::NestingTooDeep(32));
+        let mut interpreter = Interpreter::new(&mut out).with_max_depth(10);
+        assert!(interpreter.run(&program).is_ok());
+    }
+
+    #[test]
+    pub fn nesting_limit_before_running() {
+        // on the stack main gives the default limits, which must be enough
+        // to reach the limit and report it
+        let stack = crate::stack_size(DEFAULT_MAX_DEPTH).unwrap();
+        let checks = std::thread::Builder::new().stack_size(stack).spawn(|| {
+            let limit = 16000;
+            for nested in [
+                format!("n := {}1{}", "(".repeat(20_000), ")".repeat(20_000)),
+                format!("n := {}1", "-".repeat(20_000)),
+                format!("{}n := 1{}", "begin ".repeat(20_000), " end".repeat(20_000)),
+            ] {
+                let src = format!("var n: integer;\nbegin {} end.", nested);
+                let (_, errors) = parse_program(&src);
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].kind, ParseErrorKind::NestingTooDeep(limit));
+                assert!(errors[0]
+                    .to_string()
+                    .contains("statements and expressions nest more than 16000 deep"));
+            }
+
+            // a long chain parses in a loop but is a deep tree
+            let src = format!(
+                "var n: integer;\nbegin n := {}1 end.",
+                "1 + ".repeat(20_000)
+            );
+            let errors = type_errors(&src);
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].kind, TypeErrorKind::NestingTooDeep(limit));
+
+            let src = format!(
+                "var n: integer;\nbegin n := {}1{} end.",
+                "(".repeat(1000),
+                ")".repeat(1000)
+            );
+            assert!(type_errors(&src).is_empty());
+        });
+        checks.unwrap().join().unwrap();
+    }
+
+    #[test]
+    pub fn arrays() {
+        let toks = Tokenizer::new("a[1..10]").tokenize_full_src().unwrap();
+        let toks: Vec<Token> = toks.into_iter().map(|t| t.token).collect();
+        assert_eq!(
+            toks,
+            vec![
+                Token::Identifier("a".to_string()),
+                Token::OBracket,
+                Token::IntegerLiteral(1),
+                Token::DotDot,
+                Token::IntegerLiteral(10),
+                Token::CBracket,
+                Token::EOF
+            ]
+        );
+
+        let src = "const N = 3;
+var a: array[1..N] of integer;
+    grid: array[0..1, 0..1] of real;
+    d: array of integer;
+    i: integer;
+procedure bump(var x: integer);
+begin x := x + 10 end;
+begin
+  for i := 1 to N do a[i] := i * i;
+  grid[1, 0] := 2;
+  grid[0][1] := grid[1, 0] * 2;
+  SetLength(d, 2);
+  d[1] := a[3];
+  bump(d[1]);
+  SetLength(d, 3)
+end.";
+        assert_eq!(
+            run_program(src, "grid").1.unwrap().to_string(),
+            "[[0, 4], [2, 0]]"
+        );
+        assert_eq!(run_program(src, "d").1.unwrap().to_string(), "[0, 19, 0]");
+    }
+
+    #[test]
+    pub fn array_bounds() {
+        let err = run_error("var a: array[1..5] of integer; begin a[6] := 1 end.");
+        assert_eq!(err.kind, RuntimeErrorKind::IndexOutOfBounds(6, 1, 5));
+        assert_eq!(
+            err.to_string(),
+            "1:38: runtime error: index 6 out of bounds 1..5"
+        );
+
+        let err = run_error("var d: array of real; x: real; begin x := d[0] end.");
+        assert_eq!(err.kind, RuntimeErrorKind::IndexOutOfBounds(0, 0, -1));
+        assert_eq!(err.span, Span::new(42, 1, 43, 4));
+
+        let err = run_error("var a: array[1..5] of integer; begin SetLength(a, 2) end.");
+        assert_eq!(
+            err.kind,
+            RuntimeErrorKind::TypeMismatch(
+                "SetLength needs a dynamic array, found array[1..5] of integer".to_string()
+            )
+        );
+    }
+
+    #[test]
+    pub fn records() {
+        let src = "type Point = record x, y: real; end;
+     Segment = record
+       ends: array[1..2] of Point;
+       name: string
+     end;
+var s: Segment;
+    path: array[0..1] of Point;
+    p: Point;
+    dist: real;
+procedure shift(var v: real; by: real);
+begin v := v + by end;
+begin
+  s.ends[1].x := 1;
+  s.ends[2].y := 2;
+  s.name := 'diagonal';
+  path[1] := s.ends[2];
+  shift(path[1].x, 5);
+  with p, s do
+  begin
+    x := 3;
+    y := ends[2].y * 2;
+    name := 'moved'
+  end;
+  dist := p.x + p.y
+end.";
+        assert_eq!(run_program(src, "dist").1, Some(Value::Real(7.0)));
+        assert_eq!(
+            run_program(src, "path").1.unwrap().to_string(),
+            "[(x: 0; y: 0), (x: 5; y: 2)]"
+        );
+        assert_eq!(
+            run_program(src, "s").1.unwrap().to_string(),
+            "(ends: [(x: 1; y: 0), (x: 0; y: 2)]; name: moved)"
+        );
+
+        let err = run_error(&src.replace("s.name := 'diagonal'", "s.title := 'x'"));
+        assert_eq!(
+            err.kind,
+            RuntimeErrorKind::UnknownField("title".to_string())
+        );
+        assert_eq!(err.span, Span::new(313, 15, 3, 7));
+    }
+
+    #[test]
+    pub fn nested_scopes() {
+        let src = "var x, outer: integer;
+begin
+  x := 1;
+  begin
+    var x: string;
+    x := 'inner';
+    outer := 2;
+    if x = 'inner' then write('inner')
+  end;
+  outer := outer + x
+end.";
+        let (out, outer) = run_program(src, "outer");
+        assert_eq!(out, "inner");
+        assert_eq!(outer, Some(Value::Integer(3)));
+        assert_eq!(run_program(src, "x").1, Some(Value::Integer(1)));
+
+        // block locals are gone once the block ends
+        let err = run_error("begin begin var t: integer; t := 1 end; t := 2 end.");
+        assert_eq!(
+            err.kind,
+            RuntimeErrorKind::UndeclaredVariable("t".to_string())
+        );
+    }
+
+    #[test]
+    pub fn runtime_values() {
+        assert_eq!(
+            Value::Str("ab".to_string()) + Value::Str("c".to_string()),
+            Ok(Value::Str("abc".to_string()))
+        );
+        assert_eq!(-Value::Real(1.5), Ok(Value::Real(-1.5)));
+        assert_eq!(
+            Value::Str("a".to_string()) + Value::Char('b'),
+            Ok(Value::Str("ab".to_string()))
+        );
+        assert_eq!(
+            Value::Char('a') + Value::Char('b'),
+            Ok(Value::Str("ab".to_string()))
+        );
+        assert_eq!(
+            Value::Str("a".to_string()).compare(&Value::Str("b".to_string())),
+            Ok(Ordering::Less)
+        );
+        // values of different types have no order
+        assert!(Value::Boolean(true).compare(&Value::Integer(1)).is_err());
+        // integers and reals compare equal after promotion, but are not `==`
+        assert_eq!(
+            Value::Integer(1).compare(&Value::Real(1.0)),
+            Ok(Ordering::Equal)
+        );
+        assert_ne!(Value::Integer(1), Value::Real(1.0));
+        assert_eq!(
+            Value::from_literal(&Token::FloatLiteral(2.5)),
+            Some(Value::Real(2.5))
+        );
+        assert_eq!(Value::from_literal(&Token::Plus), None);
+
+        let src = "var c: char; s: string; begin s := 'ab' + 'cd' end.";
+        assert_eq!(run_program(src, "c").1, None);
+        assert_eq!(
+            run_program(src, "s").1,
+            Some(Value::Str("abcd".to_string()))
+        );
+    }
+
+    fn run_error(src: &str) -> RuntimeError {
+        let (program, errors) = parse_program(src);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let mut out = vec![];
+        Interpreter::new(&mut out).run(&program).unwrap_err()
+    }
+
+    #[test]
+    pub fn runtime_errors() {
+        let err = run_error("var a: integer;\nbegin a := 0; a := 10 mod a end.");
+        assert_eq!(err.kind, RuntimeErrorKind::DivisionByZero);
+        // the whole `10 mod a` expression
+        assert_eq!(err.span, Span::new(35, 2, 20, 8));
+
+        let err = run_error("var a: integer; begin a := 1 + true end.");
+        assert_eq!(
+            err.kind,
+            RuntimeErrorKind::TypeMismatch("cannot add integer and boolean".to_string())
+        );
+        assert_eq!(
+            err.to_string(),
+            "1:28: runtime error: type mismatch: cannot add integer and boolean"
+        );
+
+        let err = run_error("var a: integer; begin a := 9223372036854775807; a := a + 1 end.");
+        assert_eq!(err.kind, RuntimeErrorKind::Overflow);
+
+        let err = run_error("var a: integer; begin a := 2 ^ (0 - 1) end.");
+        assert_eq!(err.kind, RuntimeErrorKind::NegativeExponent);
+
+        let err = run_error("begin if 'yes' < 1 then write('x') end.");
+        assert!(matches!(err.kind, RuntimeErrorKind::TypeMismatch(_)));
+
+        let err = run_error("begin b := 1 end.");
+        assert_eq!(
+            err.kind,
+            RuntimeErrorKind::UndeclaredVariable("b".to_string())
+        );
+    }
+
+    #[test]
+    pub fn numeric_promotion() {
+        let eval = |src: &str| {
+            let toks = Tokenizer::new(src).tokenize_full_src().unwrap();
+            let expr = Parser::new(toks).expression().unwrap();
+            let mut out = vec![];
+            let value = Interpreter::new(&mut out).evaluate(&expr);
+            value
+        };
+        assert_eq!(eval("1 + 2.5"), Ok(Value::Real(3.5)));
+        assert_eq!(eval("2.5 * 2"), Ok(Value::Real(5.0)));
+        assert_eq!(eval("7 / 2"), Ok(Value::Real(3.5)));
+        assert_eq!(eval("6 / 3"), Ok(Value::Real(2.0)));
+        assert_eq!(eval("2 ^ 0.5"), Ok(Value::Real(2f64.powf(0.5))));
+        assert_eq!(eval("1 < 1.5"), Ok(Value::Boolean(true)));
+        assert_eq!(eval("2 = 2.0"), Ok(Value::Boolean(true)));
+        assert_eq!(eval("1 + 2"), Ok(Value::Integer(3)));
+
+        let src = "var r: real; begin r := 3; r := r / 2 end.";
+        assert_eq!(run_program(src, "r").1, Some(Value::Real(1.5)));
+    }
+
+    #[test]
+    pub fn integer_and_real_division() {
+        let toks = Tokenizer::new("a div b / c").tokenize_full_src().unwrap();
+        let toks: Vec<Token> = toks.into_iter().map(|t| t.token).collect();
+        assert_eq!(toks[1], Token::Div);
+        assert_eq!(toks[3], Token::Slash);
+
+        let src = "var q, r, n: integer; x: real;
+begin q := 7 div 2; r := 7 mod 2; n := (0 - 7) div 2; x := 7 / 2 end.";
+        assert_eq!(run_program(src, "q").1, Some(Value::Integer(3)));
+        assert_eq!(run_program(src, "r").1, Some(Value::Integer(1)));
+        assert_eq!(run_program(src, "n").1, Some(Value::Integer(-3)));
+        assert_eq!(run_program(src, "x").1, Some(Value::Real(3.5)));
+
+        let err = run_error("var q: integer; begin q := 7.5 div 2 end.");
+        assert_eq!(
+            err.kind,
+            RuntimeErrorKind::TypeMismatch("cannot div real and integer".to_string())
+        );
+        let err = run_error("var q: integer; begin q := 1 div 0 end.");
+        assert_eq!(err.kind, RuntimeErrorKind::DivisionByZero);
+    }
+
+    #[test]
+    pub fn logical_operators() {
+        let toks = Tokenizer::new("a or b and c = d")
+            .tokenize_full_src()
+            .unwrap();
+        let expr = Parser::new(toks).expression().unwrap();
+        assert_eq!(expr.to_string(), "(a or (b and (c = d)))");
+
+        let src = "var a, b, c: boolean; n, m: integer;
+begin
+  n := 0;
+  a := (1 < 2) and not (2 < 1);
+  b := false and (1 div n = 0);
+  c := true or (1 div n = 0);
+  n := 12 and 10;
+  m := 12 or 3;
+end.";
+        assert_eq!(run_program(src, "a").1, Some(Value::Boolean(true)));
+        assert_eq!(run_program(src, "b").1, Some(Value::Boolean(false)));
+        assert_eq!(run_program(src, "c").1, Some(Value::Boolean(true)));
+        assert_eq!(run_program(src, "n").1, Some(Value::Integer(8)));
+        assert_eq!(run_program(src, "m").1, Some(Value::Integer(15)));
+
+        let err = run_error("var a: boolean; begin a := true and 1 end.");
+        assert_eq!(
+            err.kind,
+            RuntimeErrorKind::TypeMismatch("cannot and boolean and integer".to_string())
+        );
+    }
+
+    #[test]
+    pub fn oversized_arrays() {
+        let too_large =
+            |range: &str| TypeErrorKind::Mismatch(format!("array range {} is too large", range));
+        for (decl, range) in [
+            (
+                "array[-9223372036854775807..9223372036854775807] of integer",
+                "-9223372036854775807..9223372036854775807",
+            ),
+            ("array[1..100000000000] of integer", "1..100000000000"),
+            ("array[1..10000] of array[1..10000] of integer", "1..10000"),
+        ] {
+            let src = format!("var a: {}; begin end.", decl);
+            let errors: Vec<_> = type_errors(&src).into_iter().map(|e| e.kind).collect();
+            assert_eq!(errors, vec![too_large(range)]);
+            assert_eq!(
+                run_error(&src).kind,
+                RuntimeErrorKind::TypeMismatch(format!("array range {} is too large", range))
+            );
+        }
+        let err = run_error("var d: array of integer; begin SetLength(d, 100000000000) end.");
+        assert_eq!(
+            err.kind,
+            RuntimeErrorKind::TypeMismatch("array range 0..99999999999 is too large".to_string())
+        );
+    }
+
+    fn type_errors(src: &str) -> Vec<TypeError> {
+        let (program, errors) = parse_program(src);
+        assert!(errors.is_empty(), "{:?}", errors);
+        TypeChecker::new().check(&program)
+    }
+
+    #[test]
+    pub fn type_checker_accepts_valid_programs() {
+        let src = "const N = 2 * 2;
+type Point = record x, y: real end;
+var pts: array[1..N] of Point;
+    d: array of integer;
+    i: integer;
+    total: real;
+    c: char;
+    s: string;
+function norm(const p: Point): real;
+begin
+  norm := p.x + p.y
+end;
+procedure grow(var a: array of integer; by: integer);
+begin
+  SetLength(a, by)
+end;
+begin
+  total := 0;
+  for i := 1 to N do
+  begin
+    pts[i].x := i;
+    with pts[i] do y := x / 2;
+    total := total + norm(pts[i])
+  end;
+  grow(d, 3);
+  repeat i := i - 1 until (i = 0) or (total > 100.5);
+  c := 'a';
+  if (c = 'a') or ('b' <= c) then i := 1;
+  s := 'b' + c + c
+end.";
+        assert_eq!(type_errors(src), vec![]);
+    }
+
+    #[test]
+    pub fn type_checker_reports_all_errors() {
+        let src = "var i: integer; s: string;
+function f(n: integer): integer; begin f := n end;
+procedure p(var k: integer); begin end;
+begin
+  i := 'text';
+  if i then p(3);
+  s := f(1, 2) + s;
+  i := missing
+end.";
+        let errors = type_errors(src);
+        let kinds: Vec<TypeErrorKind> = errors.iter().map(|e| e.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TypeErrorKind::Mismatch("cannot assign string to integer".to_string()),
+                TypeErrorKind::Mismatch("condition must be a boolean, found integer".to_string()),
+                TypeErrorKind::NotAssignable("3".to_string()),
+                TypeErrorKind::ArgumentCount("f".to_string(), 1, 2),
+                TypeErrorKind::UndeclaredVariable("missing".to_string()),
+            ]
+        );
+        assert_eq!(
+            errors[0].to_string(),
+            "5:8: type error: cannot assign string to integer"
+        );
+    }
+
+    #[test]
+    pub fn inferred_variable_types() {
+        let src = "var a := 1; r = 2.5; b: real := 3;
+begin
+  var s := 'hi';
+  var t := a * 2;
+  a := t + 1;
+  r := a
+end.";
+        assert!(type_errors(src).is_empty());
+        let (_, a) = run_program(src, "a");
+        assert_eq!(a, Some(Value::Integer(3)));
+        let (_, r) = run_program(src, "r");
+        assert_eq!(r, Some(Value::Real(3.0)));
+        let (_, b) = run_program(src, "b");
+        assert_eq!(b, Some(Value::Real(3.0)));
+    }
+
+    #[test]
+    pub fn inferred_types_come_from_the_expression() {
+        let src = "type Row = array of integer;
+var calls: integer := 0; n: integer; a: array[0..2] of integer;
+function mk(size: integer): Row;
+begin
+  SetLength(mk, size)
+end;
+function f: integer;
+begin
+  calls := calls + 1;
+  f := 1
+end;
+begin
+  var d := mk(0);
+  var e := mk(2);
+  SetLength(d, 3);
+  SetLength(e, 5);
+  var x := a[f];
+  n := len(d) + len(e) + x
+end.";
+        assert!(type_errors(src).is_empty());
+        let (_, n) = run_program(src, "n");
+        assert_eq!(n, Some(Value::Integer(8)));
+        // the initializer runs once
+        let (_, calls) = run_program(src, "calls");
+        assert_eq!(calls, Some(Value::Integer(1)));
+    }
+
+    #[test]
+    pub fn inferred_types_are_fixed() {
+        let src = "var a := 1; c: char := 2;
+begin
+  a := 'text';
+  a := 1.5
+end.";
+        let kinds: Vec<TypeErrorKind> = type_errors(src).into_iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TypeErrorKind::Mismatch("cannot assign integer to char".to_string()),
+                TypeErrorKind::Mismatch("cannot assign string to integer".to_string()),
+                TypeErrorKind::Mismatch("cannot assign real to integer".to_string()),
+            ]
+        );
+        // still enforced when the checker is skipped
+        let err = run_error("var a := 1;\nbegin a := 1.5 end.");
+        assert!(matches!(err.kind, RuntimeErrorKind::TypeMismatch(_)));
+        let (_, errors) = parse_program("var a;\nbegin end.");
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    pub fn builtins() {
+        let src = "var a: array[1..5] of integer; s: string; r: real; n, i: integer; c: char;
+begin
+  readln(n, r);
+  read(c);
+  readln(s);
+  for i := 1 to 5 do read(a[i]);
+  sort(a);
+  i := a[2] * 10 + a[5];
+  r := sqrt(r) + abs(-1.5) + abs(n - 10);
+  n := len(s) * 100 + len(a) * 10 + a[1];
+  s := s + endl
+end.";
+        assert!(type_errors(src).is_empty());
+        let run = |var| {
+            let (program, errors) = parse_program(src);
+            assert!(errors.is_empty(), "{:?}", errors);
+            let mut out = vec![];
+            let mut interpreter = Interpreter::new(&mut out)
+                .with_input("3 16.0 ignored\n>rest of line\n4 2\n 5\n1 3\n".as_bytes());
+            interpreter.run(&program).unwrap();
+            let value = interpreter.env().get(var).unwrap();
+            value
+        };
+        assert_eq!(run("r"), Value::Real(12.5));
+        assert_eq!(run("c"), Value::Char('>'));
+        assert_eq!(run("s"), Value::Str("rest of line\n".to_string()));
+        assert_eq!(run("n"), Value::Integer(1251));
+        assert_eq!(run("i"), Value::Integer(25));
+    }
+
+    #[test]
+    pub fn char_string_comparison() {
+        let src = "var c: char; n: integer;
+begin
+  c := 'b'; n := 0;
+  if c = 'b' then n := n + 1;
+  if 'a' < c then n := n + 10;
+  if c <> 'ba' then n := n + 100
+end.";
+        assert_eq!(run_program(src, "n").1, Some(Value::Integer(111)));
+    }
+
+    #[test]
+    pub fn builtin_argument_checks() {
+        let src = "var a: array of real; b: boolean; i: integer;
+begin
+  i := abs(1, 2);
+  i := len(3);
+  read(b);
+  sort(a);
+  i := sqrt(4)
+end.";
+        let kinds: Vec<TypeErrorKind> = type_errors(src).into_iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TypeErrorKind::ArgumentCount("abs".to_string(), 1, 2),
+                TypeErrorKind::Mismatch(
+                    "len needs a string or an array, found integer".to_string()
+                ),
+                TypeErrorKind::Mismatch("cannot read a boolean".to_string()),
+                TypeErrorKind::Mismatch("cannot assign real to integer".to_string()),
+            ]
+        );
+        let err = run_error("var r: real;\nbegin r := sqrt(-4) end.");
+        assert_eq!(err.kind, RuntimeErrorKind::NegativeRoot);
+        let err = run_error("var i: integer;\nbegin i := abs(true) end.");
+        assert_eq!(
+            err.to_string(),
+            "2:16: runtime error: type mismatch: abs needs a number, found boolean"
+        );
+    }
+
+    #[test]
+    pub fn write_formatting() {
+        let src = "var r: real; n: integer;
+begin
+  r := 3.14159; n := 42;
+  writeln(n, ' ', n:5, '|', 'ab':4, '|');
+  writeln(r:0:2, ' ', r:8:3, ' ', -r:10, ' ', true, ' ', n > 50);
+  write(1.5, endl);
+  writeln;
+  write(n div 4, ',', 'c')
+end.";
+        assert!(type_errors(src).is_empty());
+        let (out, _) = run_program(src, "n");
+        assert_eq!(
+            out,
+            "42    42|  ab|\n3.14    3.142 -3.14E+000 TRUE FALSE\n 1.50000000000000E+000\n\n10,c"
+        );
+
+        let kinds: Vec<TypeErrorKind> =
+            type_errors("var a: array[1..2] of integer;\nbegin write(a, 1:2:1, 1.5:true) end.")
+                .into_iter()
+                .map(|e| e.kind)
+                .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TypeErrorKind::Mismatch("cannot write array[1..2] of integer".to_string()),
+                TypeErrorKind::Mismatch("precision needs a real, found integer".to_string()),
+                TypeErrorKind::Mismatch("expected an integer, found boolean".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn conditional_compilation() {
+        let src = "{$DEFINE Debug}
+var x, y: integer;
+begin
+  x := 0; y := 0;
+  {$IFDEF DEBUG} x := 1; {$ELSE} x := 2; {$ENDIF}
+  {$IFNDEF debug} y := 1; {$IFDEF OTHER} y := 2; {$ENDIF} {$ELSE} y := 3; {$ENDIF}
+  {$UNDEF DEBUG}
+  {$IFDEF DEBUG} x := x + 10 {$ENDIF}
+end.";
+        assert_eq!(run_program(src, "x").1, Some(Value::Integer(1)));
+        assert_eq!(run_program(src, "y").1, Some(Value::Integer(3)));
+
+        // what is left out need not be Pascal, only comments are seen in it
+        let toks = Tokenizer::new("{$IFDEF NOPE} this is @ not 'pascal {$ENDIF} x")
+            .tokenize_full_src()
+            .unwrap();
+        assert_eq!(toks[0].token, Token::Identifier("x".to_string()));
+        let toks = Tokenizer::new("{$IFDEF NOPE} { {$ENDIF} } {$ENDIF} y")
+            .tokenize_full_src()
+            .unwrap();
+        assert_eq!(toks[0].token, Token::Identifier("y".to_string()));
+
+        let error = |src| match Tokenizer::new(src).tokenize_full_src() {
+            Err(e) => e.to_string(),
+            other => panic!("expected a lex error, got {:?}", other),
+        };
+        assert_eq!(
+            error("a {$ENDIF}"),
+            "1:3: `{$ENDIF}` without a matching `{$IFDEF}`"
+        );
+        assert_eq!(
+            error("{$IFDEF X}\na {$ELSE} b"),
+            "1:1: `{$IFDEF}` without a matching `{$ENDIF}`"
+        );
+        assert_eq!(error("{$R*}"), "1:1: invalid directive `{$R*}`");
+        assert!(error("{$I missing.inc}").starts_with("1:1: cannot include `missing.inc`: "));
+
+        // TP mode comments end at the first closing brace
+        let toks = Tokenizer::new("{$MODE TP} { a { b } c")
+            .tokenize_full_src()
+            .unwrap();
+        assert_eq!(toks[0].token, Token::Identifier("c".to_string()));
+    }
+
+    #[test]
+    pub fn check_switches_and_includes() {
+        let dir = std::env::temp_dir().join(format!("pascalpp-include-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("wrap.inc"),
+            "{$Q-}\nfunction inc(n: integer): integer;\nbegin inc := n + 1 end;\n{$Q+}",
+        )
+        .unwrap();
+        let src = "var big, wrapped, out: integer; a: array[1..3] of integer;
+{$I wrap.inc}
+begin
+  big := 9223372036854775807;
+  wrapped := inc(big);
+  {$R-}
+  a[4] := 7;
+  out := a[4] + a[0];
+  {$R+}
+  a[4] := 1
+end.";
+        let (program, errors) = Parser::new(
+            Tokenizer::new(src)
+                .with_dir(&dir)
+                .tokenize_full_src()
+                .unwrap(),
+        )
+        .program();
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        // errors in an included file name it
+        std::fs::write(dir.join("bad.inc"), "\nx := @").unwrap();
+        let err = Tokenizer::new("begin\n{$I bad.inc}\nend.")
+            .with_dir(&dir)
+            .tokenize_full_src()
+            .unwrap_err();
+        let bad = dir.join("bad.inc").display().to_string();
+        assert_eq!(
+            err.to_string(),
+            format!("{}:2:6: unexpected character `@`", bad)
+        );
+        std::fs::write(dir.join("bad.inc"), "var x integer;").unwrap();
+        let toks = Tokenizer::new("{$I bad.inc}\nbegin end.")
+            .with_dir(&dir)
+            .tokenize_full_src()
+            .unwrap();
+        let (_, errors) = Parser::new(toks).program();
+        assert_eq!(errors[0].span.file, Some(bad.as_str()));
+        assert!(errors[0].to_string().starts_with(&format!("{}:1:7: ", bad)));
+        std::fs::remove_dir_all(&dir).unwrap();
+        let mut out = vec![];
+        let mut interpreter = Interpreter::new(&mut out);
+        let err = interpreter.run(&program).unwrap_err();
+        assert_eq!(err.kind, RuntimeErrorKind::IndexOutOfBounds(4, 1, 3));
+        assert_eq!(err.span.line, 10);
+        let env = interpreter.env();
+        assert_eq!(env.get("wrapped"), Ok(Value::Integer(i64::MIN)));
+        assert_eq!(env.get("out"), Ok(Value::Integer(0)));
+
+        // checks are back on after the include, outside the function
+        let err =
+            run_error("{$Q-}{$Q+}var i: integer;\nbegin i := 9223372036854775807; i := i + 1 end.");
+        assert_eq!(err.kind, RuntimeErrorKind::Overflow);
+    }
+}
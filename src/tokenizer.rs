@@ -1,30 +1,136 @@
-use crate::helper::{self, skip_comments, tokenize_keyword, tokenize_string_literals};
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::path::PathBuf;
 
-use crate::error::DuYError;
-use crate::types::Token;
+use crate::helper::tokenize_keyword;
 
+use crate::error::{LexError, LexErrorKind};
+use crate::types::{file_name, Checks, Span, SpannedToken, Token};
+
+/// how deep `{$I}` includes may nest, deeper is taken for an include cycle
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// what directives have set so far, carried on into included files
+#[derive(Debug)]
+struct Options {
+    /// uppercased symbols from `{$DEFINE}`
+    defines: HashSet<String>,
+    /// whether `{ { } }` is one comment, turned off by `{$MODE TP}` and `{$MODE DELPHI}`
+    nested_comments: bool,
+    checks: Checks,
+    /// where `{$I}` looks for files
+    dir: PathBuf,
+    /// includes of includes currently being read
+    depth: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            defines: HashSet::new(),
+            nested_comments: true,
+            checks: Checks::default(),
+            dir: PathBuf::new(),
+            depth: 0,
+        }
+    }
+}
+
+/// an `{$IFDEF}` that is open
+struct Condition {
+    /// whether the current branch is compiled, whatever the enclosing ones say
+    active: bool,
+    seen_else: bool,
+    span: Span,
+}
+
+/// yields the tokens of a source, with directives already acted on. Whitespace
+/// and comments are left out unless asked for with [`Tokenizer::with_trivia`]
 pub struct Tokenizer {
     pos: usize,
     current_char: char,
     src: Vec<char>,
+    /// byte offset of every char, plus one entry for the end of source
+    byte_offsets: Vec<usize>,
+    /// char index where every line starts
+    line_starts: Vec<usize>,
+    /// the `{$I}` file being read, None for the program itself
+    file: Option<&'static str>,
+    options: Options,
+    /// the `{$IFDEF}`s open at the current position
+    conditions: Vec<Condition>,
+    /// tokens from includes and switches, yielded before lexing on
+    pending: VecDeque<SpannedToken>,
+    /// whether whitespace and comments are yielded
+    trivia: bool,
+    /// whether the last token lexed ends an operand, so `&` after it is `and`
+    after_operand: bool,
+    /// set at the end of source or after an error
+    finished: bool,
 }
 impl Tokenizer {
     pub fn new(src: &str) -> Self {
         let src: Vec<char> = src.chars().collect();
+
+        let mut byte_offsets = Vec::with_capacity(src.len() + 1);
+        let mut line_starts = vec![0];
+        let mut offset = 0;
+        for (i, c) in src.iter().enumerate() {
+            byte_offsets.push(offset);
+            offset += c.len_utf8();
+            if *c == '\n' {
+                line_starts.push(i + 1);
+            }
+        }
+        byte_offsets.push(offset);
+
         Tokenizer {
             pos: 0,
-            current_char: src[0],
+            current_char: src.first().copied().unwrap_or('\0'),
             src,
+            byte_offsets,
+            line_starts,
+            file: None,
+            options: Options::default(),
+            conditions: vec![],
+            pending: VecDeque::new(),
+            trivia: false,
+            after_operand: false,
+            finished: false,
         }
     }
+
+    /// also yield `Token::WhiteSpace` and `Token::Comment`
+    pub fn with_trivia(mut self) -> Self {
+        self.trivia = true;
+        self
+    }
+
+    /// resolve `{$I}` file names against `dir` instead of the working directory
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.dir = dir.into();
+        self
+    }
+
+    /// span of the chars in [start, end)
+    pub fn span(&self, start: usize, end: usize) -> Span {
+        let line = self.line_starts.partition_point(|&s| s <= start);
+        let column = start - self.line_starts[line - 1] + 1;
+        let offset = self.byte_offsets[start];
+        Span::new(offset, line, column, self.byte_offsets[end] - offset).in_file(self.file)
+    }
+
     pub fn pos_over_end(&self, pos: usize) -> bool {
         pos >= self.src.len()
     }
-    pub fn lex_next_token(&mut self) -> Result<Token, DuYError> {
+    /// the raw token at the current position, directives are not acted on
+    pub fn lex_next_token(&mut self) -> Result<SpannedToken, LexError> {
         let tok: Token;
         if self.pos_over_end(self.pos) {
-            return Ok(Token::EOF);
+            let end = self.src.len();
+            return Ok(SpannedToken::new(Token::EOF, self.span(end, end)));
         }
+        let start = self.pos;
         match self.current_char {
             '+' => tok = Token::Plus,
             '-' => tok = Token::Minus,
@@ -33,136 +139,153 @@ impl Tokenizer {
             '^' => tok = Token::Pow,
             '!' => tok = Token::Not,
             '|' => tok = Token::Or,
+            // `&17` is octal where an operand can start, `x&7` is `x and 7`
+            '&' if !self.after_operand && self.look_ahead(1).is_some_and(|c| c.is_digit(8)) => {
+                let end;
+                (tok, end) = self.number(start)?;
+                self.move_on(end - 1 - self.pos);
+            }
             '&' => tok = Token::And,
             '=' => tok = Token::Eq,
-            '/' => tok = Token::Div,
-
-            '^' => tok = Token::Pow,
-            ';' => tok = Token::SemiColon,
+            '/' if self.look_ahead(1) == Some('/') => {
+                // to the end of the line, leaving the newline
+                let end = (start..self.src.len())
+                    .find(|&i| self.src[i] == '\n')
+                    .unwrap_or(self.src.len());
+                tok = Token::Comment;
+                self.move_on(end - 1 - self.pos);
+            }
+            '/' => tok = Token::Slash,
+            ',' => tok = Token::Comma,
+            '(' if self.look_ahead(1) == Some('*') => {
+                let end = self.block_comment(start, "(*", "*)")?;
+                tok = Token::Comment;
+                self.move_on(end - 1 - self.pos);
+            }
             '(' => tok = Token::OParen,
             ')' => tok = Token::CParen,
+            '[' => tok = Token::OBracket,
+            ']' => tok = Token::CBracket,
 
             // multiple tokens
             '<' => match self.look_ahead(1) {
-                Some('=') => tok = Token::LessEq,
-                Some('>') => tok = Token::Neq,
+                Some('=') => {
+                    tok = Token::LessEq;
+                    self.move_on(1);
+                }
+                Some('>') => {
+                    tok = Token::Neq;
+                    self.move_on(1);
+                }
                 _ => tok = Token::Less,
             },
             '>' => match self.look_ahead(1) {
-                Some('=') => tok = Token::GreatEq,
+                Some('=') => {
+                    tok = Token::GreatEq;
+                    self.move_on(1);
+                }
                 _ => tok = Token::Great,
             },
+            '.' => match self.look_ahead(1) {
+                Some('.') => {
+                    tok = Token::DotDot;
+                    self.move_on(1);
+                }
+                _ => tok = Token::Dot,
+            },
             ':' => match self.look_ahead(1) {
                 Some('=') => {
                     tok = Token::Assign;
                     self.move_on(1);
                 }
-                _ => return Err(DuYError::InvalidToken),
+                _ => tok = Token::Colon,
             },
-            //string literal
-            '\'' => {
-                let final_pos = self.find_string_colon(self.pos + 1, '\'');
-                if let Some(final_pos) = final_pos {
-                    let input = &self.src[self.pos..final_pos + 1]
-                        .into_iter()
-                        .collect::<String>();
-                    tok = tokenize_string_literals(input)?;
-                    self.move_on(input.len() - 1);
-                } else {
-                    return Err(DuYError::InvalidToken);
-                }
+            //string literal, quoted parts and char codes run together
+            '\'' | '#' => {
+                let (value, end) = self.string_literal(start)?;
+                tok = Token::StringLiteral(value);
+                self.move_on(end - 1 - self.pos);
             }
-            //number literal
-            '0'..='9' => {
-                //temporary, to hold number literal
-                let mut temp = self.current_char.to_string();
-
-                // look next position
-                let mut look_ahead = self.look_ahead(temp.len());
-
-                //While not EOF or is number => push to words
-                while let Some(next) = look_ahead {
-                    if next.is_numeric() || next == '.' {
-                        temp.push(next);
-                        look_ahead = self.look_ahead(temp.len());
-                    } else {
-                        break;
-                    }
-                }
-
-                //create token from that temp
-                tok = helper::tokenize_number_literals(&temp)?;
-                self.move_on(temp.len() - 1);
+            //number literal, `$FF` and `%1010` are hex and binary
+            '0'..='9' | '$' | '%' => {
+                let end;
+                (tok, end) = self.number(start)?;
+                self.move_on(end - 1 - self.pos);
             }
 
             //identifier or keyword
             c if c.is_alphanumeric() || c == '_' => {
-                //placeholder for indentifier or keyword
-                let mut temp = self.current_char.to_string();
-                // look into next position
-                let mut look_ahead = self.look_ahead(temp.len());
-
-                //While not EOF or is letters => push to words
-                while let Some(next) = look_ahead {
-                    if next.is_alphanumeric() || next == '_' {
-                        temp.push(next);
-                        look_ahead = self.look_ahead(temp.len());
-                    } else {
-                        break;
-                    }
-                }
-                //create token from that temp
-                if temp == "true" {
-                    tok = Token::BooleanLiteral(true)
-                } else if temp == "false" {
-                    tok = Token::BooleanLiteral(false)
-                } else if let Some(_tok) = tokenize_keyword(&temp) {
-                    tok = _tok;
-                } else {
-                    tok = helper::tokenize_ident(&temp)?;
-                }
-
-                self.move_on(temp.len() - 1);
+                let end = (start..self.src.len())
+                    .find(|&i| !(self.src[i].is_alphanumeric() || self.src[i] == '_'))
+                    .unwrap_or(self.src.len());
+                let word: String = self.src[start..end].iter().collect();
+                // digits are lexed as numbers, so the word starts with a letter
+                tok = tokenize_keyword(&word).unwrap_or(Token::Identifier(word));
+                self.move_on(end - 1 - self.pos);
             }
 
+            '{' if self.look_ahead(1) == Some('$') => {
+                // directives do not nest, the first `}` ends them
+                let end = (start..self.src.len())
+                    .find(|&i| self.src[i] == '}')
+                    .ok_or_else(|| {
+                        LexError::new(
+                            LexErrorKind::UnterminatedComment,
+                            self.span(start, start + 2),
+                        )
+                    })?;
+                tok = Token::Directive(self.src[start + 2..end].iter().collect());
+                self.move_on(end - self.pos);
+            }
             '{' => {
-                let steps_to_skip =
-                    skip_comments(&self.src[self.pos..].into_iter().collect::<String>());
-                if steps_to_skip == 0 {
-                    return Err(DuYError::InvalidToken);
-                } else {
-                    self.move_on(steps_to_skip + 1);
-                    tok = Token::Comment;
-                }
+                let end = self.block_comment(start, "{", "}")?;
+                tok = Token::Comment;
+                self.move_on(end - 1 - self.pos);
+            }
+            a if a.is_whitespace() => {
+                let end = (start..self.src.len())
+                    .find(|&i| !self.src[i].is_whitespace())
+                    .unwrap_or(self.src.len());
+                tok = Token::WhiteSpace;
+                self.move_on(end - 1 - self.pos);
             }
-            a if a.is_whitespace() => tok = Token::WhiteSpace,
 
-            _ => return Err(DuYError::InvalidToken),
+            c => {
+                return Err(LexError::new(
+                    LexErrorKind::InvalidChar(c),
+                    self.span(start, start + 1),
+                ))
+            }
         }
+        if !matches!(
+            tok,
+            Token::WhiteSpace | Token::Comment | Token::Directive(_)
+        ) {
+            self.after_operand = matches!(
+                tok,
+                Token::Identifier(_)
+                    | Token::IntegerLiteral(_)
+                    | Token::FloatLiteral(_)
+                    | Token::StringLiteral(_)
+                    | Token::BooleanLiteral(_)
+                    | Token::CParen
+                    | Token::CBracket
+            );
+        }
+        // self.pos is now on the last char of the token
+        let span = self.span(start, self.pos + 1);
         self.move_on(1);
-        Ok(tok)
+        Ok(SpannedToken::new(tok, span))
     }
 
-    ///move to next non whitespace char
+    ///move `step` chars on, stopping at the end of source
     pub fn move_on(&mut self, step: usize) {
-        //move to next non whitespace
-        let mut temp_pos = self.pos + step;
-        if self.pos_over_end(temp_pos) {
-            self.pos = self.src.len();
-            return;
-        }
-
-        while self.src[temp_pos].is_whitespace() {
-            temp_pos += 1;
-        }
-        if !self.pos_over_end(temp_pos) {
-            self.pos = temp_pos;
-            self.current_char = self.src[self.pos];
-        }
+        self.pos = (self.pos + step).min(self.src.len());
+        self.current_char = self.src.get(self.pos).copied().unwrap_or('\0');
     }
 
     /// return char in the next n position
-    pub fn look_ahead(&mut self, n: usize) -> Option<char> {
+    pub fn look_ahead(&self, n: usize) -> Option<char> {
         let ahead_pos = self.pos + n;
         if self.pos_over_end(ahead_pos) {
             return None;
@@ -171,48 +294,400 @@ impl Tokenizer {
         Some(self.src[ahead_pos])
     }
 
-    pub fn next_white_space(&mut self) -> usize {
-        //TODO: refractor
-        let mut pos = self.pos + 1;
-        while !self.pos_over_end(pos) && !self.src[pos].is_whitespace() {
-            pos += 1;
+    /// every token of the source, ending with `Token::EOF`
+    pub fn tokenize_full_src(&mut self) -> Result<Vec<SpannedToken>, LexError> {
+        let mut result = self.by_ref().collect::<Result<Vec<_>, _>>()?;
+        let end = self.src.len();
+        result.push(SpannedToken::new(Token::EOF, self.span(end, end)));
+        Ok(result)
+    }
+
+    /// the next token to yield, `None` at the end of source
+    fn next_token(&mut self) -> Result<Option<SpannedToken>, LexError> {
+        loop {
+            if let Some(tok) = self.pending.pop_front() {
+                return Ok(Some(tok));
+            }
+            if self.pos_over_end(self.pos) {
+                return match self.conditions.last() {
+                    Some(open) => Err(LexError::new(
+                        LexErrorKind::UnterminatedCondition,
+                        open.span,
+                    )),
+                    None => Ok(None),
+                };
+            }
+            // inside an {$IFDEF} branch that is left out
+            if self.conditions.iter().any(|c| !c.active) && !self.starts_with(self.pos, "{$") {
+                self.skip_inactive()?;
+                continue;
+            }
+            let tok = self.lex_next_token()?;
+            match tok.token {
+                Token::Directive(text) => self.directive(&text, tok.span)?,
+                Token::WhiteSpace | Token::Comment if !self.trivia => (),
+                _ => return Ok(Some(tok)),
+            }
+        }
+    }
+
+    /// move on to the next directive through source that is left out, which
+    /// need not be Pascal. Only comments are recognised on the way, so that
+    /// a directive commented out stays out
+    fn skip_inactive(&mut self) -> Result<(), LexError> {
+        let mut pos = self.pos;
+        while pos < self.src.len() && !self.starts_with(pos, "{$") {
+            pos = if self.starts_with(pos, "//") {
+                (pos..self.src.len())
+                    .find(|&i| self.src[i] == '\n')
+                    .unwrap_or(self.src.len())
+            } else if self.starts_with(pos, "(*") {
+                self.block_comment(pos, "(*", "*)")?
+            } else if self.starts_with(pos, "{") {
+                self.block_comment(pos, "{", "}")?
+            } else {
+                pos + 1
+            };
+        }
+        self.move_on(pos - self.pos);
+        Ok(())
+    }
+
+    /// act on the directive `{$text}`, unknown directives are ignored
+    fn directive(&mut self, text: &str, span: Span) -> Result<(), LexError> {
+        let text = text.trim();
+        let name_end = text
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(text.len());
+        let name = text[..name_end].to_uppercase();
+        let arg = text[name_end..].trim();
+        let invalid = || LexError::new(LexErrorKind::InvalidDirective(text.to_string()), span);
+        let skipping = self.conditions.iter().any(|c| !c.active);
+        match name.as_str() {
+            "IFDEF" | "IFNDEF" => {
+                let defined = self.options.defines.contains(&arg.to_uppercase());
+                self.conditions.push(Condition {
+                    active: defined == (name == "IFDEF"),
+                    seen_else: false,
+                    span,
+                });
+            }
+            "ELSE" => match self.conditions.last_mut() {
+                Some(open) if !open.seen_else => {
+                    open.active = !open.active;
+                    open.seen_else = true;
+                }
+                _ => return Err(LexError::new(LexErrorKind::UnmatchedDirective(name), span)),
+            },
+            "ENDIF" => {
+                self.conditions.pop().ok_or_else(|| {
+                    LexError::new(LexErrorKind::UnmatchedDirective(name.clone()), span)
+                })?;
+            }
+            _ if skipping => (),
+            "DEFINE" | "UNDEF" => {
+                if arg.is_empty() || !arg.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                    return Err(invalid());
+                }
+                if name == "DEFINE" {
+                    self.options.defines.insert(arg.to_uppercase());
+                } else {
+                    self.options.defines.remove(&arg.to_uppercase());
+                }
+            }
+            "R" | "Q" | "RANGECHECKS" | "OVERFLOWCHECKS" => {
+                let on = match arg.to_uppercase().as_str() {
+                    "+" | "ON" => true,
+                    "-" | "OFF" => false,
+                    _ => return Err(invalid()),
+                };
+                if name.starts_with('R') {
+                    self.options.checks.range = on;
+                } else {
+                    self.options.checks.overflow = on;
+                }
+                self.pending
+                    .push_back(SpannedToken::new(Token::Checks(self.options.checks), span));
+            }
+            // {$I+} and {$I-} switch I/O checking, which is always on
+            "I" | "INCLUDE" if arg == "+" || arg == "-" => (),
+            "I" | "INCLUDE" if !arg.is_empty() => self.include(arg.trim_matches('\''), span)?,
+            "MODE" => {
+                self.options.nested_comments = match arg.to_uppercase().as_str() {
+                    "FPC" | "OBJFPC" => true,
+                    "TP" | "DELPHI" => false,
+                    _ => return Err(invalid()),
+                }
+            }
+            "I" | "INCLUDE" => return Err(invalid()),
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// queue the tokens of the file `name`, sharing the options so its
+    /// directives carry on after it. Spans of its tokens are within that file
+    /// and name it
+    fn include(&mut self, name: &str, span: Span) -> Result<(), LexError> {
+        let error =
+            |reason: String| LexError::new(LexErrorKind::Include(name.to_string(), reason), span);
+        if self.options.depth >= MAX_INCLUDE_DEPTH {
+            return Err(error("includes nest too deeply".to_string()));
+        }
+        let path = self.options.dir.join(name);
+        let src = fs::read_to_string(&path).map_err(|e| error(e.to_string()))?;
+        let mut included = Tokenizer::new(&src);
+        included.file = Some(file_name(&path.to_string_lossy()));
+        included.options = std::mem::take(&mut self.options);
+        let dir = std::mem::replace(
+            &mut included.options.dir,
+            path.parent().map(PathBuf::from).unwrap_or_default(),
+        );
+        included.options.depth += 1;
+        included.trivia = self.trivia;
+        let result = included.by_ref().collect::<Result<Vec<_>, _>>();
+        self.options = included.options;
+        self.options.dir = dir;
+        self.options.depth -= 1;
+        self.pending.extend(result?);
+        Ok(())
+    }
+
+    /// whether the chars at `pos` spell `pattern`
+    fn starts_with(&self, pos: usize, pattern: &str) -> bool {
+        pattern
+            .chars()
+            .enumerate()
+            .all(|(i, c)| self.src.get(pos + i) == Some(&c))
+    }
+
+    /// skip the comment opening at `open`, gives the char index after it.
+    /// Unless the mode says otherwise comments nest, `{ a { b } c }` is one
+    /// comment, but only with the same delimiters
+    fn block_comment(&self, open: usize, opener: &str, closer: &str) -> Result<usize, LexError> {
+        let mut depth = 0;
+        let mut pos = open;
+        while pos < self.src.len() {
+            if self.starts_with(pos, opener) && (depth == 0 || self.options.nested_comments) {
+                depth += 1;
+                pos += opener.len();
+            } else if self.starts_with(pos, closer) {
+                depth -= 1;
+                pos += closer.len();
+                if depth == 0 {
+                    return Ok(pos);
+                }
+            } else {
+                pos += 1;
+            }
         }
-        if !self.pos_over_end(pos) {
-            pos
+        Err(LexError::new(
+            LexErrorKind::UnterminatedComment,
+            self.span(open, open + opener.len()),
+        ))
+    }
+
+    /// the number literal at `start` and the char index after it: decimal
+    /// integers and reals with an optional exponent, or integers in hex
+    /// (`$FF`, `0xFF`), binary (`%1010`) or octal (`&17`). `_` separates digits
+    fn number(&self, start: usize) -> Result<(Token, usize), LexError> {
+        let radix = match (self.src[start], self.src.get(start + 1)) {
+            ('$', _) => Some((16, start + 1)),
+            ('0', Some('x' | 'X')) => Some((16, start + 2)),
+            ('%', _) => Some((2, start + 1)),
+            ('&', _) => Some((8, start + 1)),
+            _ => None,
+        };
+        let digits = |pos: usize| {
+            (pos..self.src.len())
+                .find(|&i| !(self.src[i].is_ascii_digit() || self.src[i] == '_'))
+                .unwrap_or(self.src.len())
+        };
+        let is_digit = |pos: usize| self.src.get(pos).is_some_and(|c| c.is_ascii_digit());
+
+        let mut end;
+        let mut real = false;
+        let mut unfinished = false;
+        if let Some((_, digits_start)) = radix {
+            // letters are read on, so `$FG` is one bad number rather than `$F` and `G`
+            end = (digits_start..self.src.len())
+                .find(|&i| !(self.src[i].is_alphanumeric() || self.src[i] == '_'))
+                .unwrap_or(self.src.len());
         } else {
-            0
+            end = digits(start);
+            // `1..10` is a range, any other `.` needs digits after it
+            if self.src.get(end) == Some(&'.') && is_digit(end + 1) {
+                real = true;
+                end = digits(end + 1);
+            } else if self.src.get(end) == Some(&'.') && self.src.get(end + 1) != Some(&'.') {
+                unfinished = true;
+                end += 1;
+            }
+            if matches!(self.src.get(end), Some('e' | 'E')) {
+                let sign = usize::from(matches!(self.src.get(end + 1), Some('+' | '-')));
+                if is_digit(end + 1 + sign) {
+                    real = true;
+                    end = digits(end + 1 + sign);
+                }
+            }
+            // stray letters make the whole word invalid, as in `12ab` or `1e`
+            while self
+                .src
+                .get(end)
+                .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+            {
+                end += 1;
+            }
+        }
+
+        let text: String = self.src[start..end].iter().collect();
+        let error = |kind| LexError::new(kind, self.span(start, end));
+        let body = match radix {
+            Some((_, digits_start)) => &text[digits_start - start..],
+            None => &text[..],
+        };
+        // every `_` must sit between two digits, `e` is a digit in hex
+        let separators: &[char] = match radix {
+            Some(_) => &[],
+            None => &['.', 'e', 'E', '+', '-'],
+        };
+        let misplaced = body
+            .split(separators)
+            .any(|part| part.starts_with('_') || part.ends_with('_') || part.contains("__"));
+        if body.is_empty() || misplaced || unfinished {
+            return Err(error(LexErrorKind::InvalidNumber(text)));
         }
+        let cleaned = body.replace('_', "");
+        let tok = match radix {
+            Some((radix, _)) => {
+                if !cleaned.chars().all(|c| c.is_digit(radix)) {
+                    return Err(error(LexErrorKind::InvalidNumber(text)));
+                }
+                let value = i64::from_str_radix(&cleaned, radix)
+                    .map_err(|_| error(LexErrorKind::IntegerOutOfRange(text.clone())))?;
+                Token::IntegerLiteral(value)
+            }
+            None if real => match cleaned.parse::<f64>() {
+                Ok(value) if value.is_finite() => Token::FloatLiteral(value),
+                Ok(_) => return Err(error(LexErrorKind::RealOutOfRange(text))),
+                Err(_) => return Err(error(LexErrorKind::InvalidNumber(text))),
+            },
+            None => match cleaned.parse::<i64>() {
+                Ok(value) => Token::IntegerLiteral(value),
+                Err(_) if cleaned.chars().all(|c| c.is_ascii_digit()) => {
+                    return Err(error(LexErrorKind::IntegerOutOfRange(text)))
+                }
+                Err(_) => return Err(error(LexErrorKind::InvalidNumber(text))),
+            },
+        };
+        Ok((tok, end))
     }
-    pub fn tokenize_full_src(&mut self) -> Result<Vec<Token>, DuYError> {
-        let mut result: Vec<Token> = vec![];
-        while !self.pos_over_end(self.pos) {
-            let tok = self.lex_next_token()?;
-            if !tok.is_whitespace() {
-                result.push(tok);
+
+    /// decode the run of quoted parts and `#` char codes starting at `start`,
+    /// `'it''s'#13#10` or `'tab\t'`. Gives the text and the char index after the run
+    fn string_literal(&self, start: usize) -> Result<(String, usize), LexError> {
+        let mut value = String::new();
+        let mut pos = start;
+        loop {
+            match self.src.get(pos) {
+                Some('\'') => pos = self.quoted(pos, &mut value)?,
+                Some('#') => pos = self.char_code(pos, &mut value)?,
+                _ => return Ok((value, pos)),
             }
         }
-        result.push(Token::EOF);
-        Ok(result)
     }
 
-    /// return position of first matched pattern, if cant find return None
-    pub fn find_string_colon(&self, start: usize, pattern: char) -> Option<usize> {
-        let mut temp_pos = start;
-        while self.src[temp_pos] != pattern && !temp_pos >= self.src.len() {
-            //escape the \t \n \\ \'
-            if self.src[temp_pos] == '\\' {
-                match self.src[temp_pos + 1] {
-                    '\'' | '\\' | 't' | 'n' => temp_pos += 2,
-                    _ => return None,
+    /// one `'...'` part opening at `open`, gives the char index after its closing quote
+    fn quoted(&self, open: usize, value: &mut String) -> Result<usize, LexError> {
+        let mut pos = open + 1;
+        loop {
+            match self.src.get(pos) {
+                // a doubled quote stands for one quote
+                Some('\'') if self.src.get(pos + 1) == Some(&'\'') => {
+                    value.push('\'');
+                    pos += 2;
+                }
+                Some('\'') => return Ok(pos + 1),
+                Some('\\') => {
+                    let c = match self.src.get(pos + 1) {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('0') => '\0',
+                        Some(c @ ('\\' | '\'')) => *c,
+                        None | Some('\n') => {
+                            return Err(LexError::new(
+                                LexErrorKind::UnterminatedEscape,
+                                self.span(pos, pos + 1),
+                            ))
+                        }
+                        Some(c) => {
+                            return Err(LexError::new(
+                                LexErrorKind::InvalidEscape(*c),
+                                self.span(pos, pos + 2),
+                            ))
+                        }
+                    };
+                    value.push(c);
+                    pos += 2;
+                }
+                // strings cannot span lines
+                None | Some('\n') => {
+                    return Err(LexError::new(
+                        LexErrorKind::UnterminatedString,
+                        self.span(open, pos),
+                    ))
+                }
+                Some(c) => {
+                    value.push(*c);
+                    pos += 1;
                 }
+            }
+        }
+    }
+
+    /// one `#65` or `#$41` code at `hash`, gives the char index after its digits
+    fn char_code(&self, hash: usize, value: &mut String) -> Result<usize, LexError> {
+        let hex = self.src.get(hash + 1) == Some(&'$');
+        let digits_start = if hex { hash + 2 } else { hash + 1 };
+        let mut end = digits_start;
+        while self.src.get(end).is_some_and(|c| {
+            if hex {
+                c.is_ascii_hexdigit()
             } else {
-                temp_pos += 1;
+                c.is_ascii_digit()
             }
+        }) {
+            end += 1;
         }
-        if temp_pos >= self.src.len() {
-            None
-        } else {
-            Some(temp_pos)
+        let digits: String = self.src[digits_start..end].iter().collect();
+        let radix = if hex { 16 } else { 10 };
+        let code = u32::from_str_radix(&digits, radix)
+            .ok()
+            .and_then(char::from_u32);
+        match code {
+            Some(c) => {
+                value.push(c);
+                Ok(end)
+            }
+            None => Err(LexError::new(
+                LexErrorKind::InvalidCharCode(self.src[hash..end].iter().collect()),
+                self.span(hash, end),
+            )),
+        }
+    }
+}
+
+impl Iterator for Tokenizer {
+    type Item = Result<SpannedToken, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
         }
+        let next = self.next_token().transpose();
+        // nothing sensible follows an error
+        self.finished = !matches!(next, Some(Ok(_)));
+        next
     }
 }
@@ -0,0 +1,839 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::error::{TypeError, TypeErrorKind};
+use crate::interpreter::{max_nesting, DEFAULT_MAX_DEPTH};
+use crate::types::{
+    Block, Declaration, Expr, ParamMode, Program, Routine, Span, SpannedToken, Statement,
+    StatementType, Token, Type, TypeSpec, Value, VarDecl, WriteArg,
+};
+
+/// Static type checker, walks a parsed program before it runs and collects
+/// every type error. Scoping follows the interpreter: variables and routines
+/// live in separate namespaces and a name resolves to the innermost declaration
+pub struct TypeChecker<'a> {
+    /// innermost scope last
+    scopes: Vec<Scope>,
+    /// lowercased control variables of the enclosing for loops
+    loop_vars: Vec<String>,
+    errors: Vec<TypeError>,
+    /// consulted for names none of the scopes declare
+    outer: Option<&'a dyn Declared>,
+    /// statements and expressions currently being checked
+    nesting: usize,
+    max_nesting: usize,
+}
+
+/// Names declared outside what the checker has walked, which is how the
+/// interpreter types expressions against the scopes of the running program
+pub trait Declared {
+    /// the nearest variable or constant called `name`
+    fn var(&self, name: &str) -> Option<Symbol>;
+    /// the nearest routine called `name`
+    fn routine(&self, name: &str) -> Option<Rc<Routine>>;
+    /// the nearest type alias called `name`
+    fn type_alias(&self, name: &str) -> Option<Type>;
+}
+
+/// None stands for a type that could not be worked out, the error behind
+/// it has already been reported
+type Checked = Option<Type>;
+
+#[derive(Default)]
+struct Scope {
+    vars: HashMap<String, Symbol>,
+    routines: HashMap<String, Rc<Signature>>,
+    types: HashMap<String, Type>,
+}
+
+#[derive(Clone)]
+pub enum Symbol {
+    Var(Checked),
+    /// the value is known when the constant is made of literals and other constants
+    Const((Checked, Option<Value>)),
+}
+
+struct Signature {
+    params: Vec<(ParamMode, Checked)>,
+    /// None for procedures
+    result: Option<Checked>,
+}
+
+impl<'a> TypeChecker<'a> {
+    pub fn new() -> Self {
+        TypeChecker {
+            scopes: vec![Scope::default()],
+            loop_vars: vec![],
+            errors: vec![],
+            outer: None,
+            nesting: 0,
+            max_nesting: max_nesting(DEFAULT_MAX_DEPTH),
+        }
+    }
+
+    /// refuse programs nesting deeper than the interpreter would run them
+    /// with calls nesting at most `max_depth` deep
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_nesting = max_nesting(max_depth);
+        self
+    }
+
+    /// a checker that falls back to `outer` for names it has not seen declared
+    pub fn with_outer(outer: &'a dyn Declared) -> Self {
+        TypeChecker {
+            outer: Some(outer),
+            ..TypeChecker::new()
+        }
+    }
+
+    /// every type error in `program`, in source order
+    pub fn check(mut self, program: &Program) -> Vec<TypeError> {
+        self.block(&program.block);
+        // every part at the limit is too deep, the first one says it all
+        let too_deep = self
+            .errors
+            .iter()
+            .position(|e| matches!(e.kind, TypeErrorKind::NestingTooDeep(_)));
+        if let Some(first) = too_deep {
+            self.errors.truncate(first + 1);
+        }
+        self.errors
+    }
+
+    /// the type of `expr`, None when it has none. The type a variable
+    /// declared as `var x := expr` gets
+    pub fn infer(mut self, expr: &Expr) -> Option<Type> {
+        self.expr(expr)
+    }
+
+    fn error(&mut self, kind: TypeErrorKind, span: Span) {
+        self.errors.push(TypeError::new(kind, span));
+    }
+
+    fn mismatch(&mut self, reason: String, span: Span) {
+        self.error(TypeErrorKind::Mismatch(reason), span);
+    }
+
+    /// count one more nested statement or expression, false when that is
+    /// too many, which would overflow the native stack. The span is only
+    /// worked out for the error, like in the interpreter
+    fn enter(&mut self, span: impl FnOnce() -> Span) -> bool {
+        if self.nesting >= self.max_nesting {
+            self.error(TypeErrorKind::NestingTooDeep(self.max_nesting), span());
+            return false;
+        }
+        self.nesting += 1;
+        true
+    }
+
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes
+            .last_mut()
+            .expect("the global scope is never left")
+    }
+
+    fn lookup_var(&self, name: &str) -> Option<Symbol> {
+        let key = name.to_lowercase();
+        match self.scopes.iter().rev().find_map(|s| s.vars.get(&key)) {
+            Some(symbol) => Some(symbol.clone()),
+            None => self.outer?.var(name),
+        }
+    }
+
+    fn lookup_routine(&mut self, name: &str) -> Option<Rc<Signature>> {
+        let key = name.to_lowercase();
+        let found = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|s| s.routines.get(&key))
+            .cloned();
+        match found {
+            Some(signature) => Some(signature),
+            None => {
+                let routine = self.outer?.routine(name)?;
+                Some(self.signature(&routine))
+            }
+        }
+    }
+
+    fn define(&mut self, name: &SpannedToken, symbol: Symbol) {
+        let name = name.token.to_string().to_lowercase();
+        self.scope().vars.insert(name, symbol);
+    }
+
+    fn block(&mut self, block: &Block) {
+        for declaration in &block.declarations {
+            self.declaration(declaration);
+        }
+        for statement in &block.body {
+            self.statement(statement);
+        }
+    }
+
+    fn declaration(&mut self, declaration: &Declaration) {
+        match declaration {
+            Declaration::Const(c) => {
+                let ty = self.expr(&c.value);
+                let value = self.constant(&c.value);
+                self.define(&c.name, Symbol::Const((ty, value)));
+            }
+            Declaration::Type(t) => {
+                if let Some(ty) = self.resolve(&t.spec) {
+                    let name = t.name.token.to_string().to_lowercase();
+                    self.scope().types.insert(name, ty);
+                }
+            }
+            Declaration::Var(v) => self.var_decl(v),
+            Declaration::Routine(r) => self.routine(r),
+        }
+    }
+
+    fn var_decl(&mut self, decl: &VarDecl) {
+        // checked before the names are defined, so `var x := x + 1` reads the outer x
+        let init = decl.init.as_ref().map(|expr| (self.expr(expr), expr));
+        let ty = match (&decl.spec, init) {
+            (Some(spec), Some((from, expr))) => {
+                let ty = self.resolve(spec);
+                if let (Some(to), Some(from)) = (&ty, &from) {
+                    self.expect_assignable(from, to, expr.span());
+                }
+                ty
+            }
+            (Some(spec), None) => self.resolve(spec),
+            // fixed from here on, like a written type
+            (None, Some((from, _))) => from,
+            (None, None) => None,
+        };
+        for name in &decl.names {
+            self.define(name, Symbol::Var(ty.clone()));
+        }
+    }
+
+    fn signature(&mut self, routine: &Routine) -> Rc<Signature> {
+        let mut params = vec![];
+        for param in &routine.params {
+            let ty = self.resolve(&param.spec);
+            params.extend(param.names.iter().map(|_| (param.mode, ty.clone())));
+        }
+        let result = routine.return_type.as_ref().map(|spec| self.resolve(spec));
+        Rc::new(Signature { params, result })
+    }
+
+    fn routine(&mut self, routine: &Routine) {
+        let signature = self.signature(routine);
+        // declared before the body is checked, so that it can recurse
+        let name = routine.name.token.to_string().to_lowercase();
+        self.scope().routines.insert(name, Rc::clone(&signature));
+
+        self.scopes.push(Scope::default());
+        let loop_vars = std::mem::take(&mut self.loop_vars);
+        let names = routine.params.iter().flat_map(|p| p.names.iter());
+        for (name, (mode, ty)) in names.zip(&signature.params) {
+            let symbol = match mode {
+                ParamMode::Const => Symbol::Const((ty.clone(), None)),
+                _ => Symbol::Var(ty.clone()),
+            };
+            self.define(name, symbol);
+        }
+        // the result is assigned through the function's name
+        if let Some(result) = &signature.result {
+            self.define(&routine.name, Symbol::Var(result.clone()));
+        }
+        self.block(&routine.block);
+        self.loop_vars = loop_vars;
+        self.scopes.pop();
+    }
+
+    /// the type a type spec names, builtin or declared
+    fn resolve(&mut self, spec: &TypeSpec) -> Checked {
+        match spec {
+            TypeSpec::Named(tok) => {
+                let name = tok.token.to_string();
+                let found = Type::from_name(&name).or_else(|| {
+                    let key = name.to_lowercase();
+                    let found = self.scopes.iter().rev().find_map(|s| s.types.get(&key));
+                    match found {
+                        Some(ty) => Some(ty.clone()),
+                        None => self.outer?.type_alias(&name),
+                    }
+                });
+                if found.is_none() {
+                    self.error(TypeErrorKind::UnknownType(name), tok.span);
+                }
+                found
+            }
+            TypeSpec::Array((low, high, elem)) => {
+                let span = low.span().to(high.span());
+                let bounds = match (self.constant(low), self.constant(high)) {
+                    (Some(Value::Integer(low)), Some(Value::Integer(high))) => Some((low, high)),
+                    _ => {
+                        self.error(TypeErrorKind::NotConstant, span);
+                        None
+                    }
+                };
+                let elem = self.resolve(elem);
+                let (low, high) = bounds?;
+                match Type::array(low, high, elem?) {
+                    Ok(ty) => Some(ty),
+                    Err(reason) => {
+                        self.mismatch(reason, span);
+                        None
+                    }
+                }
+            }
+            TypeSpec::DynArray(elem) => Some(Type::DynArray(Box::new(self.resolve(elem)?))),
+            TypeSpec::Record(decls) => {
+                let mut fields = vec![];
+                let mut complete = true;
+                for (names, spec) in decls {
+                    match self.resolve(spec) {
+                        Some(ty) => fields.extend(
+                            names
+                                .iter()
+                                .map(|name| (name.token.to_string(), ty.clone())),
+                        ),
+                        None => complete = false,
+                    }
+                }
+                complete.then_some(Type::Record(fields))
+            }
+        }
+    }
+
+    /// value of an expression made only of literals and constants
+    fn constant(&self, expr: &Expr) -> Option<Value> {
+        match expr {
+            Expr::Literals(tok) => match &tok.token {
+                Token::Identifier(name) => match self.lookup_var(name)? {
+                    Symbol::Const((_, value)) => value.clone(),
+                    Symbol::Var(_) => None,
+                },
+                tok => Value::from_literal(tok),
+            },
+            Expr::Unary((op, operand)) if op.token == Token::Minus => {
+                (-self.constant(operand)?).ok()
+            }
+            Expr::Binary((lhs, op, rhs)) => {
+                let (lhs, rhs) = (self.constant(lhs)?, self.constant(rhs)?);
+                match op.token {
+                    Token::Plus => lhs + rhs,
+                    Token::Minus => lhs - rhs,
+                    Token::Mul => lhs * rhs,
+                    Token::Div => lhs.int_div(rhs),
+                    Token::Mod => lhs % rhs,
+                    _ => return None,
+                }
+                .ok()
+            }
+            Expr::Grouping((expr, _)) => self.constant(expr),
+            _ => None,
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        if self.enter(|| statement.span) {
+            self.statement_nested(statement);
+            self.nesting -= 1;
+        }
+    }
+
+    fn statement_nested(&mut self, statement: &Statement) {
+        match &statement.statement_type {
+            StatementType::Assign((target, expr)) => {
+                let to = self.target(target);
+                let from = self.expr(expr);
+                if let (Some(to), Some(from)) = (to, from) {
+                    self.expect_assignable(&from, &to, expr.span());
+                }
+            }
+            StatementType::If((condition, then_branch, else_branch)) => {
+                self.condition(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            StatementType::While((condition, body)) => {
+                self.condition(condition);
+                self.statement(body);
+            }
+            StatementType::Repeat((body, condition)) => {
+                for statement in body {
+                    self.statement(statement);
+                }
+                self.condition(condition);
+            }
+            StatementType::For((name, from, _, to, body)) => {
+                let var = Expr::Literals(name.clone());
+                match self.target(&var) {
+                    Some(Type::Integer) | None => (),
+                    Some(ty) => self.mismatch(
+                        format!("for loop variable must be an integer, found {}", ty),
+                        name.span,
+                    ),
+                }
+                let (from_ty, to_ty) = (self.expr(from), self.expr(to));
+                if let (Some(from_ty), Some(to_ty)) = (from_ty, to_ty) {
+                    if from_ty != Type::Integer || to_ty != Type::Integer {
+                        self.mismatch(
+                            format!(
+                                "for loop bounds must be integers, found {} and {}",
+                                from_ty, to_ty
+                            ),
+                            from.span().to(to.span()),
+                        );
+                    }
+                }
+                self.loop_vars.push(name.token.to_string().to_lowercase());
+                self.statement(body);
+                self.loop_vars.pop();
+            }
+            StatementType::Compound(statements) => {
+                // mirrors the interpreter, only blocks declaring variables get a scope
+                let scoped = statements
+                    .iter()
+                    .any(|s| matches!(s.statement_type, StatementType::Var(_)));
+                if scoped {
+                    self.scopes.push(Scope::default());
+                }
+                for statement in statements {
+                    self.statement(statement);
+                }
+                if scoped {
+                    self.scopes.pop();
+                }
+            }
+            StatementType::With((records, body)) => {
+                for record in records {
+                    let mut scope = Scope::default();
+                    match self.target(record) {
+                        Some(Type::Record(fields)) => {
+                            for (name, ty) in fields {
+                                scope
+                                    .vars
+                                    .insert(name.to_lowercase(), Symbol::Var(Some(ty)));
+                            }
+                        }
+                        Some(ty) => self.mismatch(
+                            format!("with needs a record variable, found {}", ty),
+                            record.span(),
+                        ),
+                        None => (),
+                    }
+                    self.scopes.push(scope);
+                }
+                self.statement(body);
+                for _ in records {
+                    self.scopes.pop();
+                }
+            }
+            StatementType::Break | StatementType::Continue | StatementType::Exit => (),
+            StatementType::Var(decl) => self.var_decl(decl),
+            // a function called as a statement drops its result
+            StatementType::ProcCall((name, args)) => {
+                self.call(name, args, statement.span);
+            }
+            StatementType::Write((args, _)) => {
+                for arg in args {
+                    self.write_arg(arg);
+                }
+            }
+        }
+    }
+
+    fn write_arg(&mut self, arg: &WriteArg) {
+        let ty = self.expr(&arg.value);
+        for expr in [&arg.width, &arg.precision].into_iter().flatten() {
+            match self.expr(expr) {
+                Some(Type::Integer) | None => (),
+                Some(found) => {
+                    self.mismatch(format!("expected an integer, found {}", found), expr.span())
+                }
+            }
+        }
+        match ty {
+            Some(ty) if !ty.is_scalar() => {
+                self.mismatch(format!("cannot write {}", ty), arg.value.span())
+            }
+            Some(ty) if arg.precision.is_some() && ty != Type::Real => self.mismatch(
+                format!("precision needs a real, found {}", ty),
+                arg.value.span(),
+            ),
+            _ => (),
+        }
+    }
+
+    fn condition(&mut self, expr: &Expr) {
+        match self.expr(expr) {
+            Some(Type::Boolean) | None => (),
+            Some(ty) => self.mismatch(
+                format!("condition must be a boolean, found {}", ty),
+                expr.span(),
+            ),
+        }
+    }
+
+    fn expect_assignable(&mut self, from: &Type, to: &Type, span: Span) {
+        if !to.accepts(from) {
+            self.mismatch(format!("cannot assign {} to {}", from, to), span);
+        }
+    }
+
+    /// type of an expression that is assigned to or passed by reference,
+    /// which must name a variable or a part of one
+    fn target(&mut self, expr: &Expr) -> Checked {
+        if !self.enter(|| expr.span()) {
+            return None;
+        }
+        let ty = self.target_nested(expr);
+        self.nesting -= 1;
+        ty
+    }
+
+    fn target_nested(&mut self, expr: &Expr) -> Checked {
+        match expr {
+            Expr::Literals(SpannedToken {
+                token: Token::Identifier(name),
+                span,
+            }) => match self.lookup_var(name) {
+                Some(Symbol::Var(ty)) => {
+                    if self.loop_vars.contains(&name.to_lowercase()) {
+                        self.mismatch(
+                            format!(
+                                "cannot assign to loop variable `{}` inside its for loop",
+                                name
+                            ),
+                            *span,
+                        );
+                    }
+                    ty
+                }
+                Some(Symbol::Const(_)) => {
+                    self.error(TypeErrorKind::NotAssignable(name.clone()), *span);
+                    None
+                }
+                None if self.lookup_routine(name).is_some() => {
+                    self.error(TypeErrorKind::NotAssignable(name.clone()), *span);
+                    None
+                }
+                None => {
+                    self.error(TypeErrorKind::UndeclaredVariable(name.clone()), *span);
+                    None
+                }
+            },
+            Expr::Index((array, index, span)) => {
+                let array = self.target(array);
+                self.index(array, index, *span)
+            }
+            Expr::Field((record, field)) => {
+                let record = self.target(record);
+                self.field(record, field)
+            }
+            _ => {
+                self.expr(expr);
+                self.error(TypeErrorKind::NotAssignable(expr.to_string()), expr.span());
+                None
+            }
+        }
+    }
+
+    fn index(&mut self, array: Checked, index: &Expr, span: Span) -> Checked {
+        match self.expr(index) {
+            Some(Type::Integer) | None => (),
+            Some(ty) => self.mismatch(
+                format!("array index must be an integer, found {}", ty),
+                index.span(),
+            ),
+        }
+        match array? {
+            Type::Array((_, _, elem)) | Type::DynArray(elem) => Some(*elem),
+            ty => {
+                self.mismatch(format!("cannot index {}", ty), span);
+                None
+            }
+        }
+    }
+
+    fn field(&mut self, record: Checked, field: &SpannedToken) -> Checked {
+        let name = field.token.to_string();
+        match record? {
+            Type::Record(fields) => {
+                let found = fields
+                    .into_iter()
+                    .find(|(f, _)| f.eq_ignore_ascii_case(&name))
+                    .map(|(_, ty)| ty);
+                if found.is_none() {
+                    self.error(TypeErrorKind::UnknownField(name), field.span);
+                }
+                found
+            }
+            ty => {
+                self.mismatch(format!("{} has no field `{}`", ty, name), field.span);
+                None
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Checked {
+        if !self.enter(|| expr.span()) {
+            return None;
+        }
+        let ty = self.expr_nested(expr);
+        self.nesting -= 1;
+        ty
+    }
+
+    fn expr_nested(&mut self, expr: &Expr) -> Checked {
+        match expr {
+            Expr::Literals(tok) => match &tok.token {
+                Token::Identifier(name) => match self.lookup_var(name) {
+                    Some(Symbol::Var(ty)) | Some(Symbol::Const((ty, _))) => ty,
+                    // a function without parameters can be called without parentheses
+                    None if self.lookup_routine(name).is_some() => {
+                        self.function_call(tok, &[], tok.span)
+                    }
+                    None => {
+                        self.error(TypeErrorKind::UndeclaredVariable(name.clone()), tok.span);
+                        None
+                    }
+                },
+                Token::IntegerLiteral(_) => Some(Type::Integer),
+                Token::FloatLiteral(_) => Some(Type::Real),
+                Token::StringLiteral(_) | Token::Endl => Some(Type::Str),
+                Token::BooleanLiteral(_) => Some(Type::Boolean),
+                _ => None,
+            },
+            Expr::Unary((op, operand)) => {
+                let ty = self.expr(operand)?;
+                let valid = match op.token {
+                    Token::Minus => matches!(ty, Type::Integer | Type::Real),
+                    _ => matches!(ty, Type::Integer | Type::Boolean),
+                };
+                if !valid {
+                    let reason = match op.token {
+                        Token::Minus => format!("cannot negate {}", ty),
+                        _ => format!("cannot apply not to {}", ty),
+                    };
+                    self.mismatch(reason, expr.span());
+                    return None;
+                }
+                Some(ty)
+            }
+            Expr::Binary((lhs, op, rhs)) => {
+                let (lhs, rhs) = (self.expr(lhs), self.expr(rhs));
+                let (lhs, rhs) = (lhs?, rhs?);
+                let ty = binary(&op.token, &lhs, &rhs);
+                if ty.is_none() {
+                    self.mismatch(
+                        format!("cannot {} {} and {}", verb(&op.token), lhs, rhs),
+                        expr.span(),
+                    );
+                }
+                ty
+            }
+            Expr::Grouping((expr, _)) => self.expr(expr),
+            Expr::Call((name, args, span)) => self.function_call(name, args, *span),
+            Expr::Index((array, index, span)) => {
+                let array = self.expr(array);
+                self.index(array, index, *span)
+            }
+            Expr::Field((record, field)) => {
+                let record = self.expr(record);
+                self.field(record, field)
+            }
+        }
+    }
+
+    /// a call used as an expression, which must produce a value
+    fn function_call(&mut self, name: &SpannedToken, args: &[Expr], span: Span) -> Checked {
+        match self.call(name, args, span)? {
+            Some(result) => result,
+            None => {
+                self.mismatch(
+                    format!("procedure `{}` does not return a value", name.token),
+                    span,
+                );
+                None
+            }
+        }
+    }
+
+    /// check the arguments of a call, gives the result type of a function and
+    /// Some(None) for a procedure. The outer None means the call is broken
+    fn call(&mut self, name: &SpannedToken, args: &[Expr], span: Span) -> Option<Option<Checked>> {
+        let name_str = name.token.to_string();
+        let Some(signature) = self.lookup_routine(&name_str) else {
+            return self.builtin(name, args, span);
+        };
+        if args.len() != signature.params.len() {
+            self.error(
+                TypeErrorKind::ArgumentCount(name_str, signature.params.len(), args.len()),
+                span,
+            );
+            return None;
+        }
+        for ((mode, ty), arg) in signature.params.iter().zip(args) {
+            match mode {
+                ParamMode::Var => {
+                    let found = self.target(arg);
+                    if let (Some(ty), Some(found)) = (ty, found) {
+                        if *ty != found {
+                            self.mismatch(
+                                format!("var parameter needs a {} variable, found {}", ty, found),
+                                arg.span(),
+                            );
+                        }
+                    }
+                }
+                _ => {
+                    let found = self.expr(arg);
+                    if let (Some(ty), Some(found)) = (ty, found) {
+                        self.expect_assignable(&found, ty, arg.span());
+                    }
+                }
+            }
+        }
+        Some(signature.result.clone())
+    }
+
+    /// procedures and functions every program can call without declaring
+    fn builtin(
+        &mut self,
+        name: &SpannedToken,
+        args: &[Expr],
+        span: Span,
+    ) -> Option<Option<Checked>> {
+        let name_str = name.token.to_string();
+        let lowercase = name_str.to_lowercase();
+        let expected = match lowercase.as_str() {
+            "setlength" => Some(2),
+            "sort" | "sqrt" | "abs" | "len" => Some(1),
+            _ => None,
+        };
+        if let Some(expected) = expected.filter(|&n| n != args.len()) {
+            self.error(
+                TypeErrorKind::ArgumentCount(name_str, expected, args.len()),
+                span,
+            );
+            return None;
+        }
+        match lowercase.as_str() {
+            "setlength" => {
+                let [array, length] = args else {
+                    unreachable!("argument count checked above");
+                };
+                match self.target(array) {
+                    Some(Type::DynArray(_)) | None => (),
+                    Some(ty) => self.mismatch(
+                        format!("SetLength needs a dynamic array, found {}", ty),
+                        array.span(),
+                    ),
+                }
+                match self.expr(length) {
+                    Some(Type::Integer) | None => (),
+                    Some(ty) => {
+                        self.mismatch(format!("expected an integer, found {}", ty), length.span())
+                    }
+                }
+                Some(None)
+            }
+            "read" | "readln" => {
+                for arg in args {
+                    match self.target(arg) {
+                        Some(ty) if !ty.is_scalar() || ty == Type::Boolean => {
+                            self.mismatch(format!("cannot read a {}", ty), arg.span())
+                        }
+                        _ => (),
+                    }
+                }
+                Some(None)
+            }
+            "sort" => {
+                match self.target(&args[0]) {
+                    Some(Type::Array((_, _, elem)) | Type::DynArray(elem)) if elem.is_scalar() => {}
+                    Some(ty) => self.mismatch(
+                        format!("sort needs an array of scalars, found {}", ty),
+                        args[0].span(),
+                    ),
+                    None => (),
+                }
+                Some(None)
+            }
+            "sqrt" | "abs" | "len" => {
+                let arg = &args[0];
+                let result = match (lowercase.as_str(), self.expr(arg)?) {
+                    ("sqrt", Type::Integer | Type::Real) => Type::Real,
+                    ("abs", ty @ (Type::Integer | Type::Real)) => ty,
+                    ("len", Type::Str | Type::Array(_) | Type::DynArray(_)) => Type::Integer,
+                    (_, ty) => {
+                        let what = match lowercase.as_str() {
+                            "len" => "a string or an array",
+                            _ => "a number",
+                        };
+                        self.mismatch(
+                            format!("{} needs {}, found {}", lowercase, what, ty),
+                            arg.span(),
+                        );
+                        return Some(Some(None));
+                    }
+                };
+                Some(Some(Some(result)))
+            }
+            _ => {
+                for arg in args {
+                    self.expr(arg);
+                }
+                self.error(TypeErrorKind::UnknownRoutine(name_str), name.span);
+                None
+            }
+        }
+    }
+}
+
+/// result type of a binary operator, None when the operands don't fit it.
+/// Mirrors the operators on runtime values
+fn binary(op: &Token, lhs: &Type, rhs: &Type) -> Option<Type> {
+    use Type::{Boolean, Char, Integer, Real, Str};
+    let numeric = |t: &Type| matches!(t, Integer | Real);
+    let both_numeric = numeric(lhs) && numeric(rhs);
+    match (op, lhs, rhs) {
+        (Token::Plus | Token::Minus | Token::Mul | Token::Pow, Integer, Integer) => Some(Integer),
+        (Token::Plus | Token::Minus | Token::Mul | Token::Pow, _, _) if both_numeric => Some(Real),
+        (Token::Plus, Str | Char, Str | Char) => Some(Str),
+        (Token::Slash, _, _) if both_numeric => Some(Real),
+        (Token::Div | Token::Mod, Integer, Integer) => Some(Integer),
+        (Token::Mod, Real, Real) => Some(Real),
+        (Token::And | Token::Or, Boolean, Boolean) => Some(Boolean),
+        (Token::And | Token::Or, Integer, Integer) => Some(Integer),
+        (
+            Token::Eq | Token::Neq | Token::Great | Token::GreatEq | Token::Less | Token::LessEq,
+            _,
+            _,
+        ) if both_numeric || (*lhs == Boolean && *rhs == Boolean) => Some(Boolean),
+        // quoted literals are strings, so chars and strings compare with
+        // each other as `accepts` lets them be assigned to each other
+        (
+            Token::Eq | Token::Neq | Token::Great | Token::GreatEq | Token::Less | Token::LessEq,
+            Char | Str,
+            Char | Str,
+        ) => Some(Boolean),
+        _ => None,
+    }
+}
+
+/// what an operator does, as worded in type errors
+fn verb(op: &Token) -> &'static str {
+    match op {
+        Token::Plus => "add",
+        Token::Minus => "subtract",
+        Token::Mul => "multiply",
+        Token::Slash => "divide",
+        Token::Div => "div",
+        Token::Mod => "mod",
+        Token::Pow => "raise",
+        Token::And => "and",
+        Token::Or => "or",
+        _ => "compare",
+    }
+}
@@ -1,51 +1,33 @@
 use core::fmt;
 
-use super::token::Token;
-use crate::types::Pow;
+use super::span::Span;
+use super::token::SpannedToken;
 
 #[derive(Debug)]
 pub enum Expr {
-    Unary((Token, Box<Expr>)),
-    Binary((Box<Expr>, Token, Box<Expr>)),
-    Literals(Token),
-    Grouping(Box<Expr>),
+    Unary((SpannedToken, Box<Expr>)),
+    Binary((Box<Expr>, SpannedToken, Box<Expr>)),
+    Literals(SpannedToken),
+    /// the span covers both parentheses
+    Grouping((Box<Expr>, Span)),
+    /// function call, the span runs from the name to the closing parenthesis
+    Call((SpannedToken, Vec<Expr>, Span)),
+    /// `array[index]`, the span runs to the closing bracket
+    Index((Box<Expr>, Box<Expr>, Span)),
+    /// `record.field`, the field is a Token::Identifier
+    Field((Box<Expr>, SpannedToken)),
 }
 impl Expr {
-    pub fn eval(&self) -> Token {
+    /// source location of the whole expression
+    pub fn span(&self) -> Span {
         match self {
-            Expr::Unary((ops, expr)) => match ops {
-                Token::Minus => Token::IntegerLiteral(0) - expr.eval(),
-                Token::Not => !expr.eval(),
-                _ => panic!(),
-            },
-
-            Expr::Binary((lhs, ops, rhs)) => match ops {
-                Token::Plus => lhs.eval() + rhs.eval(),
-                Token::Minus => lhs.eval() - rhs.eval(),
-
-                Token::Mul => lhs.eval() * rhs.eval(),
-                Token::Div => lhs.eval() / rhs.eval(),
-                Token::Mod => lhs.eval() % rhs.eval(),
-
-                Token::Pow => lhs.eval().pow_token(rhs.eval()),
-
-                Token::Eq => Token::BooleanLiteral(lhs.eval() == rhs.eval()),
-                Token::Neq => Token::BooleanLiteral(lhs.eval() != rhs.eval()),
-                Token::Great => Token::BooleanLiteral(lhs.eval() > rhs.eval()),
-                Token::GreatEq => Token::BooleanLiteral(lhs.eval() >= rhs.eval()),
-                Token::Less => Token::BooleanLiteral(lhs.eval() < rhs.eval()),
-                Token::LessEq => Token::BooleanLiteral(lhs.eval() <= rhs.eval()),
-                // Token::And => lhs.eval() && rhs.eval(),
-                _ => panic!("Unsupported operator"),
-            },
-            Expr::Literals(value) => match value {
-                Token::IntegerLiteral(i) => Token::IntegerLiteral(*i),
-                Token::FloatLiteral(f) => Token::FloatLiteral(*f),
-                Token::StringLiteral(s) => Token::StringLiteral(s.clone()),
-                Token::BooleanLiteral(b) => Token::BooleanLiteral(*b),
-                _ => panic!("Unsupported literal"),
-            },
-            Expr::Grouping(expr) => expr.eval(),
+            Expr::Unary((ops, expr)) => ops.span.to(expr.span()),
+            Expr::Binary((lhs, _, rhs)) => lhs.span().to(rhs.span()),
+            Expr::Literals(value) => value.span,
+            Expr::Grouping((_, span)) => *span,
+            Expr::Call((_, _, span)) => *span,
+            Expr::Index((_, _, span)) => *span,
+            Expr::Field((record, field)) => record.span().to(field.span),
         }
     }
 }
@@ -55,7 +37,13 @@ impl fmt::Display for Expr {
             Expr::Unary((t, e)) => write!(f, "<{} {}>", t, e),
             Expr::Binary((l, t, r)) => write!(f, "({} {} {})", l, t, r),
             Expr::Literals(t) => write!(f, "{}", t),
-            Expr::Grouping(e) => write!(f, "({})", e),
+            Expr::Grouping((e, _)) => write!(f, "({})", e),
+            Expr::Call((name, args, _)) => {
+                let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+            Expr::Index((array, index, _)) => write!(f, "{}[{}]", array, index),
+            Expr::Field((record, field)) => write!(f, "{}.{}", record, field),
         }
     }
 }
@@ -1,6 +1,14 @@
 mod expr;
-mod token;
+mod program;
+mod span;
 mod statement;
+mod token;
+mod ty;
+mod value;
 pub use expr::*;
+pub use program::*;
+pub use span::*;
 pub use statement::*;
 pub use token::*;
+pub use ty::*;
+pub use value::*;
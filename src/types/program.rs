@@ -0,0 +1,87 @@
+use std::rc::Rc;
+
+use crate::types::{Expr, SpannedToken, Statement};
+
+#[derive(Debug)]
+pub struct Program {
+    pub block: Block,
+}
+
+/// declarations followed by a `begin ... end` body,
+/// used for both the main program and routines
+#[derive(Debug, Default)]
+pub struct Block {
+    /// in source order, a name must be declared before it is used
+    pub declarations: Vec<Declaration>,
+    pub body: Vec<Statement>,
+}
+
+#[derive(Debug)]
+pub enum Declaration {
+    Const(ConstDecl),
+    Type(TypeDecl),
+    Var(VarDecl),
+    /// shared with the scope the routine is declared in
+    Routine(Rc<Routine>),
+}
+
+/// `Name = expr;`
+#[derive(Debug)]
+pub struct ConstDecl {
+    pub name: SpannedToken,
+    pub value: Expr,
+}
+
+/// `Name = type;`
+#[derive(Debug)]
+pub struct TypeDecl {
+    pub name: SpannedToken,
+    pub spec: TypeSpec,
+}
+
+/// `a, b: type;`, `a := expr;` or both, `a: type := expr;`
+#[derive(Debug)]
+pub struct VarDecl {
+    pub names: Vec<SpannedToken>,
+    /// None when the type is inferred from the initializer
+    pub spec: Option<TypeSpec>,
+    /// evaluated once, its value assigned to every name
+    pub init: Option<Expr>,
+}
+
+/// a type as written in the source
+#[derive(Debug)]
+pub enum TypeSpec {
+    Named(SpannedToken),                          //Token::Identifier
+    Array((Box<Expr>, Box<Expr>, Box<TypeSpec>)), //array[low..high] of type, one per dimension
+    DynArray(Box<TypeSpec>),                      //array of type
+    Record(Vec<(Vec<SpannedToken>, TypeSpec)>),   //record a, b: type; ... end
+}
+
+/// function or procedure declaration, procedures have no return type
+#[derive(Debug)]
+pub struct Routine {
+    pub name: SpannedToken,
+    pub params: Vec<Param>,
+    pub return_type: Option<TypeSpec>,
+    pub block: Block,
+}
+
+/// a group of parameters sharing a type, `a, b: integer`
+#[derive(Debug)]
+pub struct Param {
+    pub mode: ParamMode,
+    pub names: Vec<SpannedToken>,
+    pub spec: TypeSpec,
+}
+
+/// how arguments are passed to a parameter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamMode {
+    /// a copy the routine may change
+    Value,
+    /// `var`, the caller's variable itself
+    Var,
+    /// `const`, a copy the routine may not change
+    Const,
+}
@@ -0,0 +1,65 @@
+use core::fmt;
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+/// Location of a piece of source text.
+/// `offset` and `len` are in bytes, `line` and `column` start from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+    /// the `{$I}` file the text is in, None for the program's own source
+    pub file: Option<&'static str>,
+}
+
+/// `name` as a file name spans can carry. Spans are copied around freely,
+/// so every distinct name is kept for the rest of the run
+pub fn file_name(name: &str) -> &'static str {
+    static NAMES: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+    let mut names = NAMES.lock().unwrap_or_else(|e| e.into_inner());
+    match names.get(name) {
+        Some(name) => name,
+        None => {
+            let name: &'static str = Box::leak(name.into());
+            names.insert(name);
+            name
+        }
+    }
+}
+
+impl Span {
+    pub fn new(offset: usize, line: usize, column: usize, len: usize) -> Self {
+        Span {
+            offset,
+            line,
+            column,
+            len,
+            file: None,
+        }
+    }
+
+    /// the same span, in the file `name`
+    pub fn in_file(self, name: Option<&'static str>) -> Self {
+        Span { file: name, ..self }
+    }
+
+    /// span covering from the start of self to the end of other
+    pub fn to(&self, other: Span) -> Span {
+        let end = (other.offset + other.len).max(self.offset + self.len);
+        Span {
+            len: end - self.offset,
+            ..*self
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = self.file {
+            write!(f, "{}:", file)?;
+        }
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
@@ -1,24 +1,63 @@
-use crate::types::{Expr, Token};
+use crate::types::{Expr, Span, SpannedToken, VarDecl};
 
 #[derive(Debug)]
 pub struct Statement {
-    expression: Expr,
-    statement_type: StatementType,
+    pub statement_type: StatementType,
+    pub span: Span,
+    /// the checks switched on where the statement starts
+    pub checks: Checks,
 }
 impl Statement {
-    pub fn new(expression: Expr, statement_type: StatementType) -> Self {
+    pub fn new(statement_type: StatementType, span: Span, checks: Checks) -> Self {
         Statement {
-            expression,
             statement_type,
+            span,
+            checks,
+        }
+    }
+}
+
+/// runtime checks the `{$R}` and `{$Q}` switches turn on and off
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Checks {
+    /// array indexes within bounds, `{$R+}`
+    pub range: bool,
+    /// integer arithmetic without overflow, `{$Q+}`
+    pub overflow: bool,
+}
+
+impl Default for Checks {
+    fn default() -> Self {
+        Checks {
+            range: true,
+            overflow: true,
         }
     }
 }
 
 #[derive(Debug)]
 pub enum StatementType {
-    If(Vec<Statement>),
-    Var(Token),      //Token::identifier
-    ProcCall(Token), //Token::identifier   print statement
-    While(Vec<Statement>),
-    For(Vec<Statement>),
+    Assign((Expr, Expr)), //variable or element := expr
+    If((Expr, Box<Statement>, Option<Box<Statement>>)),
+    While((Expr, Box<Statement>)),
+    Repeat((Vec<Statement>, Expr)), //repeat ... until expr
+    For((SpannedToken, Expr, bool, Expr, Box<Statement>)), //for Token::Identifier := from to|downto to do, true for downto
+    Compound(Vec<Statement>),                              //begin ... end
+    ProcCall((SpannedToken, Vec<Expr>)),                   //a user or builtin procedure
+    Write((Vec<WriteArg>, bool)),                          //write(args), writeln(args) when true
+    With((Vec<Expr>, Box<Statement>)),                     //with records do statement
+    Break,
+    Continue,
+    Exit,         //leaves the current routine, or the program
+    Var(VarDecl), //var a, b: type   local to the enclosing begin ... end
+}
+
+/// one argument of write, `value:width:precision`
+#[derive(Debug)]
+pub struct WriteArg {
+    pub value: Expr,
+    /// the least number of characters written, padded on the left
+    pub width: Option<Expr>,
+    /// digits after the decimal point, reals only
+    pub precision: Option<Expr>,
 }
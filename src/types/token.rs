@@ -1,18 +1,37 @@
 use core::fmt;
-use std::ops;
+
+use super::span::Span;
+use super::statement::Checks;
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, PartialOrd)]
 pub enum Token {
     // keywords
+    Program,
+    Const,
+    Type,
     Var,
     If,
     Then,
     Else,
     While,
     For,
+    To,
+    Downto,
     Do,
+    Repeat,
+    Until,
+    Break,
+    Continue,
+    Exit,
     Function,
+    Procedure,
     Begin,
     End,
+    Array,
+    Of,
+    Record,
+    With,
 
     //Builtin functions
     Write,
@@ -23,19 +42,27 @@ pub enum Token {
     Len,
     Endl,
 
-    SemiColon, // ;
-    Comment,   // // or {}
+    SemiColon,         // ;
+    Colon,             // :
+    Comma,             // ,
+    Dot,               // .
+    DotDot,            // ..
+    Comment,           // // line, { block } or (* block *)
+    Directive(String), // {$name args}, the text after the $
+    Checks(Checks),    // the checks in effect from here on, set by a directive
     EOF,
     WhiteSpace,
     OParen,
     CParen,
-    //TODO: add array square brackets
+    OBracket, // [
+    CBracket, // ]
 
     // operators
     Plus,   // +
     Minus,  // -
     Mul,    // *
     Assign, // :=
+    Slash,  // /
     Div,    // div
     Mod,    // mod
 
@@ -60,12 +87,20 @@ pub enum Token {
     BooleanLiteral(bool),
 }
 
-impl Token {
-    pub fn is_whitespace(&self) -> bool {
-        match self {
-            Token::WhiteSpace => true,
-            _ => false,
-        }
+/// A token together with where it was found in the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+impl SpannedToken {
+    pub fn new(token: Token, span: Span) -> Self {
+        SpannedToken { token, span }
+    }
+}
+impl fmt::Display for SpannedToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.token)
     }
 }
 impl Clone for Token {
@@ -86,13 +121,34 @@ impl Clone for Token {
             Token::Write => Token::Write,
             Token::Read => Token::Read,
             Token::Sqrt => Token::Sqrt,
+            Token::Program => Token::Program,
+            Token::Const => Token::Const,
+            Token::Type => Token::Type,
+            Token::Procedure => Token::Procedure,
+            Token::Colon => Token::Colon,
+            Token::Comma => Token::Comma,
+            Token::Dot => Token::Dot,
+            Token::DotDot => Token::DotDot,
+            Token::OBracket => Token::OBracket,
+            Token::CBracket => Token::CBracket,
+            Token::Array => Token::Array,
+            Token::Of => Token::Of,
+            Token::Record => Token::Record,
+            Token::With => Token::With,
             Token::Var => Token::Var,
             Token::If => Token::If,
             Token::Then => Token::Then,
             Token::Else => Token::Else,
             Token::While => Token::While,
             Token::For => Token::For,
+            Token::To => Token::To,
+            Token::Downto => Token::Downto,
             Token::Do => Token::Do,
+            Token::Repeat => Token::Repeat,
+            Token::Until => Token::Until,
+            Token::Break => Token::Break,
+            Token::Continue => Token::Continue,
+            Token::Exit => Token::Exit,
             Token::Function => Token::Function,
             Token::Begin => Token::Begin,
             Token::End => Token::End,
@@ -100,6 +156,7 @@ impl Clone for Token {
             Token::Minus => Token::Minus,
             Token::Mul => Token::Mul,
             Token::Assign => Token::Assign,
+            Token::Slash => Token::Slash,
             Token::Div => Token::Div,
             Token::Mod => Token::Mod,
             Token::Pow => Token::Pow,
@@ -108,6 +165,8 @@ impl Clone for Token {
             Token::EOF => Token::EOF,
             Token::SemiColon => Token::SemiColon,
             Token::Comment => Token::Comment,
+            Token::Directive(s) => Token::Directive(s.to_string()),
+            Token::Checks(c) => Token::Checks(*c),
             Token::WhiteSpace => Token::WhiteSpace,
             Token::Not => Token::Not,
             Token::Eq => Token::Eq,
@@ -123,9 +182,65 @@ impl Clone for Token {
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Token::Program => write!(f, "program"),
+            Token::Const => write!(f, "const"),
+            Token::Type => write!(f, "type"),
+            Token::Procedure => write!(f, "procedure"),
+            Token::Colon => write!(f, ":"),
+            Token::Comma => write!(f, ","),
+            Token::Dot => write!(f, "."),
+            Token::DotDot => write!(f, ".."),
+            Token::OBracket => write!(f, "["),
+            Token::CBracket => write!(f, "]"),
+            Token::Array => write!(f, "array"),
+            Token::Of => write!(f, "of"),
+            Token::Record => write!(f, "record"),
+            Token::With => write!(f, "with"),
             Token::Var => write!(f, "var"),
             Token::If => write!(f, "if"),
             Token::Then => write!(f, "then"),
+            Token::Else => write!(f, "else"),
+            Token::While => write!(f, "while"),
+            Token::For => write!(f, "for"),
+            Token::To => write!(f, "to"),
+            Token::Downto => write!(f, "downto"),
+            Token::Do => write!(f, "do"),
+            Token::Repeat => write!(f, "repeat"),
+            Token::Until => write!(f, "until"),
+            Token::Break => write!(f, "break"),
+            Token::Continue => write!(f, "continue"),
+            Token::Exit => write!(f, "exit"),
+            Token::Function => write!(f, "function"),
+            Token::Begin => write!(f, "begin"),
+            Token::End => write!(f, "end"),
+            Token::Write => write!(f, "write"),
+            Token::Read => write!(f, "read"),
+            Token::Sqrt => write!(f, "sqrt"),
+            Token::Abs => write!(f, "abs"),
+            Token::Sort => write!(f, "sort"),
+            Token::Len => write!(f, "len"),
+            Token::Endl => write!(f, "endl"),
+            Token::SemiColon => write!(f, ";"),
+            Token::OParen => write!(f, "("),
+            Token::CParen => write!(f, ")"),
+            Token::Plus => write!(f, "+"),
+            Token::Minus => write!(f, "-"),
+            Token::Mul => write!(f, "*"),
+            Token::Assign => write!(f, ":="),
+            Token::Slash => write!(f, "/"),
+            Token::Div => write!(f, "div"),
+            Token::Mod => write!(f, "mod"),
+            Token::Pow => write!(f, "^"),
+            Token::And => write!(f, "and"),
+            Token::Or => write!(f, "or"),
+            Token::Not => write!(f, "not"),
+            Token::Eq => write!(f, "="),
+            Token::Neq => write!(f, "<>"),
+            Token::Great => write!(f, ">"),
+            Token::GreatEq => write!(f, ">="),
+            Token::Less => write!(f, "<"),
+            Token::LessEq => write!(f, "<="),
+            Token::Identifier(s) => write!(f, "{}", s),
             Token::IntegerLiteral(i) => write!(f, "{}", i),
             Token::FloatLiteral(fl) => write!(f, "{}", fl),
             Token::StringLiteral(s) => write!(f, "{}", s),
@@ -134,89 +249,3 @@ impl fmt::Display for Token {
         }
     }
 }
-
-impl ops::Add<Token> for Token {
-    type Output = Token;
-    fn add(self, other: Token) -> Token {
-        match (self, other) {
-            (Token::IntegerLiteral(i), Token::IntegerLiteral(j)) => Token::IntegerLiteral(i + j),
-            (Token::FloatLiteral(i), Token::FloatLiteral(j)) => Token::FloatLiteral(i + j),
-            _ => panic!("Cannot add"),
-        }
-    }
-}
-//implement subtract for Token
-impl ops::Sub<Token> for Token {
-    type Output = Token;
-    fn sub(self, other: Token) -> Token {
-        match (self, other) {
-            (Token::IntegerLiteral(i), Token::IntegerLiteral(j)) => Token::IntegerLiteral(i - j),
-            (Token::FloatLiteral(i), Token::FloatLiteral(j)) => Token::FloatLiteral(i - j),
-            _ => panic!("Cannot subtract"),
-        }
-    }
-}
-
-// implement Mul for token
-impl ops::Mul<Token> for Token {
-    type Output = Token;
-    fn mul(self, other: Token) -> Token {
-        match (self, other) {
-            (Token::IntegerLiteral(i), Token::IntegerLiteral(j)) => Token::IntegerLiteral(i * j),
-            (Token::FloatLiteral(i), Token::FloatLiteral(j)) => Token::FloatLiteral(i * j),
-            _ => panic!("Cannot multiply"),
-        }
-    }
-}
-
-// implement Div for token
-impl ops::Div<Token> for Token {
-    type Output = Token;
-    fn div(self, other: Token) -> Token {
-        match (self, other) {
-            (Token::IntegerLiteral(i), Token::IntegerLiteral(j)) => Token::IntegerLiteral(i / j),
-            (Token::FloatLiteral(i), Token::FloatLiteral(j)) => Token::FloatLiteral(i / j),
-            _ => panic!("Cannot divide"),
-        }
-    }
-}
-//implement Mod for Token
-impl ops::Rem<Token> for Token {
-    type Output = Token;
-    fn rem(self, other: Token) -> Token {
-        match (self, other) {
-            (Token::IntegerLiteral(i), Token::IntegerLiteral(j)) => Token::IntegerLiteral(i % j),
-            (Token::FloatLiteral(i), Token::FloatLiteral(j)) => Token::FloatLiteral(i % j),
-            _ => panic!("Cannot mod"),
-        }
-    }
-}
-// implement Pow for token
-pub trait Pow<Rhs = Self> {
-    type Output;
-    #[must_use]
-    fn pow_token(self, Rhs: Self) -> Self::Output;
-}
-
-impl Pow<Token> for Token {
-    type Output = Token;
-    fn pow_token(self, other: Token) -> Token {
-        match (self, other) {
-            (Token::IntegerLiteral(i), Token::IntegerLiteral(j)) => {
-                Token::IntegerLiteral(i.pow(j as u32))
-            }
-            (Token::FloatLiteral(i), Token::FloatLiteral(j)) => Token::FloatLiteral(i.powf(j)),
-            _ => panic!("Cannot pow"),
-        }
-    }
-}
-
-impl ops::Not for Token {
-    type Output = Token;
-    fn not(self) -> Token {
-        match self {
-            Token::BooleanLiteral(b) => Token::BooleanLiteral(!b),
-            _ => panic!("Cannot not"),
-        }
-    }
-}
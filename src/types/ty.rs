@@ -0,0 +1,246 @@
+use core::fmt;
+
+use super::value::{Array, Selector, Value};
+use crate::error::RuntimeErrorKind;
+
+/// most scalars one array may hold, counting those in nested arrays and records
+pub const MAX_ARRAY_ELEMENTS: usize = 1 << 24;
+
+/// Type of a declared variable
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Integer,
+    Real,
+    Boolean,
+    Char,
+    Str,
+    /// static array with inclusive bounds
+    Array((i64, i64, Box<Type>)),
+    /// dynamic array indexed from 0, sized by SetLength
+    DynArray(Box<Type>),
+    /// field names as declared, in declaration order
+    Record(Vec<(String, Type)>),
+}
+
+impl Type {
+    /// builtin type names, Pascal names are case insensitive
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name.to_lowercase().as_str() {
+            "integer" => Some(Type::Integer),
+            "real" => Some(Type::Real),
+            "boolean" => Some(Type::Boolean),
+            "char" => Some(Type::Char),
+            "string" => Some(Type::Str),
+            _ => None,
+        }
+    }
+
+    /// `array[low..high] of elem`, or why the range cannot hold an array
+    pub fn array(low: i64, high: i64, elem: Type) -> Result<Type, String> {
+        if high < low {
+            return Err(format!("array range {}..{} is empty", low, high));
+        }
+        let ty = Type::Array((low, high, Box::new(elem)));
+        match ty.size() {
+            Some(_) => Ok(ty),
+            None => Err(format!("array range {}..{} is too large", low, high)),
+        }
+    }
+
+    /// how many scalars a value of the type holds, at least one per element
+    /// so arrays of empty records count. None past `MAX_ARRAY_ELEMENTS`
+    fn size(&self) -> Option<usize> {
+        let size = match self {
+            Type::Array((low, high, elem)) => usize::try_from(high.checked_sub(*low)?)
+                .ok()?
+                .checked_add(1)?
+                .checked_mul(elem.size()?.max(1))?,
+            Type::Record(fields) => fields
+                .iter()
+                .try_fold(0usize, |sum, (_, ty)| sum.checked_add(ty.size()?))?,
+            _ => 1,
+        };
+        (size <= MAX_ARRAY_ELEMENTS).then_some(size)
+    }
+
+    /// `value` converted for storing into a variable of this type:
+    /// integers widen to real, one char strings narrow to char
+    pub fn coerce(&self, value: Value) -> Result<Value, RuntimeErrorKind> {
+        match (self, value) {
+            (Type::Integer, v @ Value::Integer(_)) => Ok(v),
+            (Type::Real, Value::Integer(i)) => Ok(Value::Real(i as f64)),
+            (Type::Real, v @ Value::Real(_)) => Ok(v),
+            (Type::Boolean, v @ Value::Boolean(_)) => Ok(v),
+            (Type::Char, v @ Value::Char(_)) => Ok(v),
+            (Type::Char, Value::Str(s)) if s.chars().count() == 1 => {
+                Ok(Value::Char(s.chars().next().unwrap()))
+            }
+            (Type::Str, v @ Value::Str(_)) => Ok(v),
+            (Type::Str, Value::Char(c)) => Ok(Value::Str(c.to_string())),
+            (Type::Array((low, high, elem)), Value::Array(a))
+                if a.low == *low && a.high() == *high =>
+            {
+                elem.coerce_array(a)
+            }
+            (Type::DynArray(elem), Value::Array(a)) if a.low == 0 => elem.coerce_array(a),
+            (Type::Record(types), Value::Record(fields))
+                if types.len() == fields.len()
+                    && types
+                        .iter()
+                        .zip(&fields)
+                        .all(|((t, _), (f, _))| t.eq_ignore_ascii_case(f)) =>
+            {
+                let fields = types
+                    .iter()
+                    .zip(fields)
+                    .map(|((_, ty), (name, value))| Ok((name, ty.coerce(value)?)))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::Record(fields))
+            }
+            (ty, v) => Err(RuntimeErrorKind::TypeMismatch(format!(
+                "cannot assign {} to {}",
+                v.type_name(),
+                ty
+            ))),
+        }
+    }
+}
+
+impl Type {
+    /// whether values of type `from` can be stored in this type,
+    /// the static side of `coerce`
+    pub fn accepts(&self, from: &Type) -> bool {
+        match (from, self) {
+            (f, t) if f == t => true,
+            (Type::Integer, Type::Real) | (Type::Char, Type::Str) | (Type::Str, Type::Char) => true,
+            (Type::Array((fl, fh, fe)), Type::Array((tl, th, te))) => {
+                fl == tl && fh == th && te.accepts(fe)
+            }
+            (Type::Array((0, _, fe)), Type::DynArray(te)) => te.accepts(fe),
+            (Type::DynArray(fe), Type::DynArray(te)) => te.accepts(fe),
+            (Type::Record(from), Type::Record(to)) => {
+                from.len() == to.len()
+                    && from.iter().zip(to).all(|((fname, fty), (tname, tty))| {
+                        fname.eq_ignore_ascii_case(tname) && tty.accepts(fty)
+                    })
+            }
+            _ => false,
+        }
+    }
+
+    fn coerce_array(&self, array: Array) -> Result<Value, RuntimeErrorKind> {
+        let elements = array
+            .elements
+            .into_iter()
+            .map(|e| self.coerce(e))
+            .collect::<Result<_, _>>()?;
+        Ok(Value::Array(Array {
+            low: array.low,
+            elements,
+        }))
+    }
+
+    /// a single value with an order, anything but arrays and records
+    pub fn is_scalar(&self) -> bool {
+        !matches!(self, Type::Array(_) | Type::DynArray(_) | Type::Record(_))
+    }
+
+    /// the type of a value, for constants and elements read out of
+    /// temporaries. Arrays are taken as static arrays over their current
+    /// bounds, None when empty
+    pub fn of(value: &Value) -> Option<Type> {
+        match value {
+            Value::Integer(_) => Some(Type::Integer),
+            Value::Real(_) => Some(Type::Real),
+            Value::Boolean(_) => Some(Type::Boolean),
+            Value::Char(_) => Some(Type::Char),
+            Value::Str(_) => Some(Type::Str),
+            Value::Array(array) => {
+                let elem = Type::of(array.elements.first()?)?;
+                Some(Type::Array((array.low, array.high(), Box::new(elem))))
+            }
+            Value::Record(fields) => fields
+                .iter()
+                .map(|(name, v)| Some((name.clone(), Type::of(v)?)))
+                .collect::<Option<_>>()
+                .map(Type::Record),
+        }
+    }
+
+    /// value of a fresh variable, arrays start out allocated and zeroed,
+    /// scalars start out uninitialized
+    pub fn initial(&self) -> Option<Value> {
+        match self {
+            Type::Array(_) | Type::DynArray(_) | Type::Record(_) => Some(self.zero()),
+            _ => None,
+        }
+    }
+
+    /// the zero value of the type, as held by fresh array elements
+    pub fn zero(&self) -> Value {
+        match self {
+            Type::Integer => Value::Integer(0),
+            Type::Real => Value::Real(0.0),
+            Type::Boolean => Value::Boolean(false),
+            Type::Char => Value::Char('\0'),
+            Type::Str => Value::Str(String::new()),
+            // `Type::array` keeps the length small enough to allocate
+            Type::Array((low, high, elem)) => Value::Array(Array {
+                low: *low,
+                elements: vec![elem.zero(); high.abs_diff(*low) as usize + 1],
+            }),
+            Type::DynArray(_) => Value::Array(Array {
+                low: 0,
+                elements: vec![],
+            }),
+            Type::Record(fields) => Value::Record(
+                fields
+                    .iter()
+                    .map(|(name, ty)| (name.clone(), ty.zero()))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// type of the part of a value of this type `selector` picks
+    pub fn select(&self, selector: &Selector) -> Result<&Type, RuntimeErrorKind> {
+        match (self, selector) {
+            (Type::Array((_, _, elem)), Selector::Index(_)) => Ok(elem),
+            (Type::DynArray(elem), Selector::Index(_)) => Ok(elem),
+            (Type::Record(fields), Selector::Field(name)) => fields
+                .iter()
+                .find(|(field, _)| field.eq_ignore_ascii_case(name))
+                .map(|(_, ty)| ty)
+                .ok_or_else(|| RuntimeErrorKind::UnknownField(name.clone())),
+            (ty, Selector::Index(_)) => Err(RuntimeErrorKind::TypeMismatch(format!(
+                "cannot index {}",
+                ty
+            ))),
+            (ty, Selector::Field(name)) => Err(RuntimeErrorKind::TypeMismatch(format!(
+                "{} has no field `{}`",
+                ty, name
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Integer => write!(f, "integer"),
+            Type::Real => write!(f, "real"),
+            Type::Boolean => write!(f, "boolean"),
+            Type::Char => write!(f, "char"),
+            Type::Str => write!(f, "string"),
+            Type::Array((low, high, elem)) => write!(f, "array[{}..{}] of {}", low, high, elem),
+            Type::DynArray(elem) => write!(f, "array of {}", elem),
+            Type::Record(fields) => {
+                write!(f, "record")?;
+                for (name, ty) in fields {
+                    write!(f, " {}: {};", name, ty)?;
+                }
+                write!(f, " end")
+            }
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use core::fmt;
+use std::cmp::Ordering;
+use std::ops;
+
+use super::token::Token;
+use crate::error::RuntimeErrorKind;
+
+/// A runtime value, what expressions evaluate to and variables hold
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    Real(f64),
+    Boolean(bool),
+    Str(String),
+    Char(char),
+    Array(Array),
+    /// fields in declaration order
+    Record(Vec<(String, Value)>),
+}
+
+/// elements of an array indexed from `low`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Array {
+    pub low: i64,
+    pub elements: Vec<Value>,
+}
+
+/// one step from a structured value to a part of it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selector {
+    Index(i64),
+    Field(String),
+}
+
+impl Value {
+    /// the part of this value `selector` picks
+    pub fn select(&self, selector: &Selector) -> Result<&Value, RuntimeErrorKind> {
+        match (self, selector) {
+            (Value::Array(a), Selector::Index(i)) => {
+                let at = a.position(*i)?;
+                Ok(&a.elements[at])
+            }
+            (Value::Record(fields), Selector::Field(name)) => fields
+                .iter()
+                .find(|(field, _)| field.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value)
+                .ok_or_else(|| RuntimeErrorKind::UnknownField(name.clone())),
+            (v, Selector::Index(_)) => Err(cannot_index(v)),
+            (v, Selector::Field(name)) => Err(no_fields(v, name)),
+        }
+    }
+
+    pub fn select_mut(&mut self, selector: &Selector) -> Result<&mut Value, RuntimeErrorKind> {
+        match (self, selector) {
+            (Value::Array(a), Selector::Index(i)) => {
+                let at = a.position(*i)?;
+                Ok(&mut a.elements[at])
+            }
+            (Value::Record(fields), Selector::Field(name)) => fields
+                .iter_mut()
+                .find(|(field, _)| field.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value)
+                .ok_or_else(|| RuntimeErrorKind::UnknownField(name.clone())),
+            (v, Selector::Index(_)) => Err(cannot_index(v)),
+            (v, Selector::Field(name)) => Err(no_fields(v, name)),
+        }
+    }
+
+    /// value of a literal token, None for any other token
+    pub fn from_literal(tok: &Token) -> Option<Value> {
+        match tok {
+            Token::IntegerLiteral(i) => Some(Value::Integer(*i)),
+            Token::FloatLiteral(f) => Some(Value::Real(*f)),
+            Token::StringLiteral(s) => Some(Value::Str(s.clone())),
+            Token::BooleanLiteral(b) => Some(Value::Boolean(*b)),
+            Token::Endl => Some(Value::Str("\n".to_string())),
+            _ => None,
+        }
+    }
+
+    /// name of the value's type, as used in error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Integer(_) => "integer",
+            Value::Real(_) => "real",
+            Value::Boolean(_) => "boolean",
+            Value::Str(_) => "string",
+            Value::Char(_) => "char",
+            Value::Array(_) => "array",
+            Value::Record(_) => "record",
+        }
+    }
+
+    /// Pascal `div`, integer division truncating towards zero
+    pub fn int_div(self, other: Value) -> ValueResult {
+        match (self, other) {
+            (Value::Integer(_), Value::Integer(0)) => Err(RuntimeErrorKind::DivisionByZero),
+            (Value::Integer(i), Value::Integer(j)) => i
+                .checked_div(j)
+                .map(Value::Integer)
+                .ok_or(RuntimeErrorKind::Overflow),
+            (i, j) => Err(mismatch("div", &i, &j)),
+        }
+    }
+
+    /// the value as `write` prints it, right aligned in `width` characters.
+    /// Reals print in scientific notation unless given a `precision`
+    pub fn format(
+        &self,
+        width: Option<usize>,
+        precision: Option<usize>,
+    ) -> Result<String, RuntimeErrorKind> {
+        let text = match (self, precision) {
+            (Value::Real(r), Some(digits)) if r.is_finite() => format!("{:.*}", digits, r),
+            // as many digits as the width leaves room for, sign slot included
+            (Value::Real(r), None) => scientific(*r, width.map_or(14, |w| w.max(9) - 8)),
+            (Value::Real(r), Some(_)) => scientific(*r, 0),
+            (Value::Boolean(b), None) => b.to_string().to_uppercase(),
+            (v @ (Value::Integer(_) | Value::Str(_) | Value::Char(_)), None) => v.to_string(),
+            (v, None) => {
+                return Err(RuntimeErrorKind::TypeMismatch(format!(
+                    "cannot write {}",
+                    v.type_name()
+                )))
+            }
+            (v, Some(_)) => {
+                return Err(RuntimeErrorKind::TypeMismatch(format!(
+                    "precision needs a real, found {}",
+                    v.type_name()
+                )))
+            }
+        };
+        Ok(format!("{:>1$}", text, width.unwrap_or(0)))
+    }
+
+    /// the integer result of `op` wrapped around on overflow, as with `{$Q-}`.
+    /// None when `op` is not integer arithmetic or there is no result
+    pub fn wrapping(op: &Token, lhs: &Value, rhs: &Value) -> Option<Value> {
+        let (Value::Integer(i), Value::Integer(j)) = (lhs, rhs) else {
+            return None;
+        };
+        let result = match op {
+            Token::Plus => i.wrapping_add(*j),
+            Token::Minus => i.wrapping_sub(*j),
+            Token::Mul => i.wrapping_mul(*j),
+            Token::Div if *j != 0 => i.wrapping_div(*j),
+            Token::Mod if *j != 0 => i.wrapping_rem(*j),
+            Token::Pow => i.wrapping_pow(u32::try_from(*j).ok()?),
+            _ => return None,
+        };
+        Some(Value::Integer(result))
+    }
+
+    /// order of two values of the same scalar type, integers and reals
+    /// count as the same type and so do chars and strings. This is not
+    /// `PartialOrd`, which would have to agree with the structural `==`
+    pub fn compare(&self, other: &Value) -> Result<Ordering, RuntimeErrorKind> {
+        let ordering = match (self, other) {
+            (Value::Integer(i), Value::Integer(j)) => i.partial_cmp(j),
+            (Value::Real(i), Value::Real(j)) => i.partial_cmp(j),
+            (Value::Integer(i), Value::Real(j)) => (*i as f64).partial_cmp(j),
+            (Value::Real(i), Value::Integer(j)) => i.partial_cmp(&(*j as f64)),
+            (Value::Boolean(i), Value::Boolean(j)) => i.partial_cmp(j),
+            (Value::Str(i), Value::Str(j)) => i.partial_cmp(j),
+            (Value::Char(i), Value::Char(j)) => i.partial_cmp(j),
+            (Value::Char(i), Value::Str(j)) => i.to_string().partial_cmp(j),
+            (Value::Str(i), Value::Char(j)) => i.partial_cmp(&j.to_string()),
+            _ => None,
+        };
+        ordering.ok_or_else(|| mismatch("compare", self, other))
+    }
+}
+
+impl Array {
+    /// highest valid index, below `low` when the array is empty
+    pub fn high(&self) -> i64 {
+        self.low + (self.elements.len() as i64 - 1)
+    }
+
+    /// where index `i` sits in `elements`, checking the array bounds
+    fn position(&self, i: i64) -> Result<usize, RuntimeErrorKind> {
+        if i < self.low || i > self.high() {
+            return Err(RuntimeErrorKind::IndexOutOfBounds(i, self.low, self.high()));
+        }
+        Ok((i - self.low) as usize)
+    }
+}
+
+fn cannot_index(v: &Value) -> RuntimeErrorKind {
+    RuntimeErrorKind::TypeMismatch(format!("cannot index {}", v.type_name()))
+}
+
+fn no_fields(v: &Value, field: &str) -> RuntimeErrorKind {
+    RuntimeErrorKind::TypeMismatch(format!("{} has no field `{}`", v.type_name(), field))
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::Real(r) => write!(f, "{}", r),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Char(c) => write!(f, "{}", c),
+            Value::Array(a) => {
+                let elements: Vec<String> = a.elements.iter().map(|e| e.to_string()).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            Value::Record(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", name, value))
+                    .collect();
+                write!(f, "({})", fields.join("; "))
+            }
+        }
+    }
+}
+
+/// Pascal's `-1.50E+000`, a space stands in for the sign of positive numbers
+fn scientific(r: f64, digits: usize) -> String {
+    let sign = if r.is_sign_negative() { '-' } else { ' ' };
+    if r.is_nan() {
+        return "Nan".to_string();
+    } else if r.is_infinite() {
+        return format!("{}Inf", if sign == '-' { '-' } else { '+' });
+    }
+    let formatted = format!("{:.*e}", digits, r.abs());
+    let (mantissa, exponent) = formatted
+        .split_once('e')
+        .expect("rust always writes an exponent");
+    let exponent: i32 = exponent.parse().expect("the exponent is an integer");
+    let exp_sign = if exponent < 0 { '-' } else { '+' };
+    format!("{}{}E{}{:03}", sign, mantissa, exp_sign, exponent.abs())
+}
+
+/// what every arithmetic operator on values produces
+pub type ValueResult = Result<Value, RuntimeErrorKind>;
+
+/// Pascal numeric promotion: an integer next to a real widens to real
+fn promote(lhs: Value, rhs: Value) -> (Value, Value) {
+    match (lhs, rhs) {
+        (Value::Integer(i), Value::Real(j)) => (Value::Real(i as f64), Value::Real(j)),
+        (Value::Real(i), Value::Integer(j)) => (Value::Real(i), Value::Real(j as f64)),
+        pair => pair,
+    }
+}
+
+fn mismatch(op: &str, lhs: &Value, rhs: &Value) -> RuntimeErrorKind {
+    RuntimeErrorKind::TypeMismatch(format!(
+        "cannot {} {} and {}",
+        op,
+        lhs.type_name(),
+        rhs.type_name()
+    ))
+}
+
+impl ops::Add<Value> for Value {
+    type Output = ValueResult;
+    fn add(self, other: Value) -> ValueResult {
+        match promote(self, other) {
+            (Value::Integer(i), Value::Integer(j)) => i
+                .checked_add(j)
+                .map(Value::Integer)
+                .ok_or(RuntimeErrorKind::Overflow),
+            (Value::Real(i), Value::Real(j)) => Ok(Value::Real(i + j)),
+            (Value::Str(i), Value::Str(j)) => Ok(Value::Str(i + &j)),
+            // chars concatenate with strings and each other
+            (Value::Str(mut i), Value::Char(j)) => {
+                i.push(j);
+                Ok(Value::Str(i))
+            }
+            (Value::Char(i), Value::Str(j)) => Ok(Value::Str(i.to_string() + &j)),
+            (Value::Char(i), Value::Char(j)) => Ok(Value::Str(String::from_iter([i, j]))),
+            (i, j) => Err(mismatch("add", &i, &j)),
+        }
+    }
+}
+
+impl ops::Sub<Value> for Value {
+    type Output = ValueResult;
+    fn sub(self, other: Value) -> ValueResult {
+        match promote(self, other) {
+            (Value::Integer(i), Value::Integer(j)) => i
+                .checked_sub(j)
+                .map(Value::Integer)
+                .ok_or(RuntimeErrorKind::Overflow),
+            (Value::Real(i), Value::Real(j)) => Ok(Value::Real(i - j)),
+            (i, j) => Err(mismatch("subtract", &i, &j)),
+        }
+    }
+}
+
+impl ops::Mul<Value> for Value {
+    type Output = ValueResult;
+    fn mul(self, other: Value) -> ValueResult {
+        match promote(self, other) {
+            (Value::Integer(i), Value::Integer(j)) => i
+                .checked_mul(j)
+                .map(Value::Integer)
+                .ok_or(RuntimeErrorKind::Overflow),
+            (Value::Real(i), Value::Real(j)) => Ok(Value::Real(i * j)),
+            (i, j) => Err(mismatch("multiply", &i, &j)),
+        }
+    }
+}
+
+impl ops::Div<Value> for Value {
+    type Output = ValueResult;
+    fn div(self, other: Value) -> ValueResult {
+        // `/` is real division even on two integers
+        let (lhs, rhs) = match (self, other) {
+            (Value::Integer(i), Value::Integer(j)) => {
+                (Value::Real(i as f64), Value::Real(j as f64))
+            }
+            pair => promote(pair.0, pair.1),
+        };
+        match (lhs, rhs) {
+            (Value::Real(_), Value::Real(0.0)) => Err(RuntimeErrorKind::DivisionByZero),
+            (Value::Real(i), Value::Real(j)) => Ok(Value::Real(i / j)),
+            (i, j) => Err(mismatch("divide", &i, &j)),
+        }
+    }
+}
+
+impl ops::Rem<Value> for Value {
+    type Output = ValueResult;
+    fn rem(self, other: Value) -> ValueResult {
+        match (self, other) {
+            (Value::Integer(_), Value::Integer(0)) => Err(RuntimeErrorKind::DivisionByZero),
+            (Value::Integer(i), Value::Integer(j)) => i
+                .checked_rem(j)
+                .map(Value::Integer)
+                .ok_or(RuntimeErrorKind::Overflow),
+            (Value::Real(_), Value::Real(0.0)) => Err(RuntimeErrorKind::DivisionByZero),
+            (Value::Real(i), Value::Real(j)) => Ok(Value::Real(i % j)),
+            (i, j) => Err(mismatch("mod", &i, &j)),
+        }
+    }
+}
+
+impl ops::Neg for Value {
+    type Output = ValueResult;
+    fn neg(self) -> ValueResult {
+        match self {
+            Value::Integer(i) => i
+                .checked_neg()
+                .map(Value::Integer)
+                .ok_or(RuntimeErrorKind::Overflow),
+            Value::Real(r) => Ok(Value::Real(-r)),
+            v => Err(RuntimeErrorKind::TypeMismatch(format!(
+                "cannot negate {}",
+                v.type_name()
+            ))),
+        }
+    }
+}
+
+/// logical and on booleans, bitwise and on integers
+impl ops::BitAnd<Value> for Value {
+    type Output = ValueResult;
+    fn bitand(self, other: Value) -> ValueResult {
+        match (self, other) {
+            (Value::Boolean(i), Value::Boolean(j)) => Ok(Value::Boolean(i && j)),
+            (Value::Integer(i), Value::Integer(j)) => Ok(Value::Integer(i & j)),
+            (i, j) => Err(mismatch("and", &i, &j)),
+        }
+    }
+}
+
+/// logical or on booleans, bitwise or on integers
+impl ops::BitOr<Value> for Value {
+    type Output = ValueResult;
+    fn bitor(self, other: Value) -> ValueResult {
+        match (self, other) {
+            (Value::Boolean(i), Value::Boolean(j)) => Ok(Value::Boolean(i || j)),
+            (Value::Integer(i), Value::Integer(j)) => Ok(Value::Integer(i | j)),
+            (i, j) => Err(mismatch("or", &i, &j)),
+        }
+    }
+}
+
+/// logical not on booleans, bitwise complement on integers
+impl ops::Not for Value {
+    type Output = ValueResult;
+    fn not(self) -> ValueResult {
+        match self {
+            Value::Boolean(b) => Ok(Value::Boolean(!b)),
+            Value::Integer(i) => Ok(Value::Integer(!i)),
+            v => Err(RuntimeErrorKind::TypeMismatch(format!(
+                "cannot apply not to {}",
+                v.type_name()
+            ))),
+        }
+    }
+}
+
+// ^ has no std::ops trait
+pub trait Pow<Rhs = Self> {
+    type Output;
+    #[must_use]
+    fn pow_value(self, rhs: Rhs) -> Self::Output;
+}
+
+impl Pow<Value> for Value {
+    type Output = ValueResult;
+    fn pow_value(self, other: Value) -> ValueResult {
+        match promote(self, other) {
+            (Value::Integer(_), Value::Integer(j)) if j < 0 => {
+                Err(RuntimeErrorKind::NegativeExponent)
+            }
+            (Value::Integer(i), Value::Integer(j)) => u32::try_from(j)
+                .ok()
+                .and_then(|j| i.checked_pow(j))
+                .map(Value::Integer)
+                .ok_or(RuntimeErrorKind::Overflow),
+            (Value::Real(i), Value::Real(j)) => Ok(Value::Real(i.powf(j))),
+            (i, j) => Err(mismatch("raise", &i, &j)),
+        }
+    }
+}
@@ -0,0 +1,22 @@
+program Hello;
+{ a small program, run by default when no file is given }
+const
+  Limit = 3;
+var
+  i, total: integer;
+
+procedure greet(name: string);
+begin
+  writeln('hi ', name);
+end;
+
+begin
+  greet('there');
+  total := 0;
+  for i := 1 to Limit do
+    total := total + i;
+  if total = 6 then
+    write('sum is six')
+  else
+    write('something is off')
+end.